@@ -0,0 +1,92 @@
+use crate::error::AppError;
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::{header::AUTHORIZATION, request::Parts},
+};
+use std::{env, sync::Arc};
+
+// bearer token for the operator endpoints; `None` keeps them closed
+#[derive(Clone, Default)]
+pub struct AdminToken(Option<Arc<str>>);
+
+impl AdminToken {
+    pub fn new(token: impl Into<Arc<str>>) -> Self {
+        Self(Some(token.into()))
+    }
+
+    //  ADMIN_API_TOKEN
+    pub fn from_env() -> Self {
+        env::var("ADMIN_API_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .map(Self::new)
+            .unwrap_or_default()
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.0.is_some()
+    }
+
+    fn matches(&self, candidate: &str) -> bool {
+        let Some(token) = &self.0 else {
+            return false;
+        };
+
+        // constant time over the candidate so the comparison doesn't leak a prefix
+        let (token, candidate) = (token.as_bytes(), candidate.as_bytes());
+        let mut diff = token.len() ^ candidate.len();
+        for (i, byte) in candidate.iter().enumerate() {
+            diff |= usize::from(byte ^ token.get(i).copied().unwrap_or(0));
+        }
+        diff == 0
+    }
+}
+
+impl std::fmt::Debug for AdminToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(if self.0.is_some() {
+            "AdminToken(..)"
+        } else {
+            "AdminToken(None)"
+        })
+    }
+}
+
+// Guard for admin routes: `Authorization: Bearer <ADMIN_API_TOKEN>`.
+//
+// Missing credentials are 401, a wrong token is 403, and when no token is
+// configured every admin route answers 403.
+#[derive(Debug)]
+pub struct RequireAdmin;
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequireAdmin
+where
+    S: Send + Sync,
+    AdminToken: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let admin_token = AdminToken::from_ref(state);
+        if !admin_token.is_configured() {
+            return Err(AppError::Forbidden(
+                "Admin endpoints are disabled, set ADMIN_API_TOKEN".to_string(),
+            ));
+        }
+
+        let candidate = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| AppError::Unauthorized("Missing bearer token".to_string()))?;
+
+        if admin_token.matches(candidate.trim()) {
+            Ok(RequireAdmin)
+        } else {
+            Err(AppError::Forbidden("Invalid admin token".to_string()))
+        }
+    }
+}
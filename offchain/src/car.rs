@@ -0,0 +1,295 @@
+use crate::cid::{format_binary, is_binary_v0, push_varint, read_varint};
+use anyhow::{Context, Result};
+use ciborium::Value;
+use sha2::{Digest, Sha256};
+
+const MULTIHASH_IDENTITY: u64 = 0x00;
+const MULTIHASH_SHA2_256: u64 = 0x12;
+
+// CBOR tag DAG-CBOR uses for links
+const CID_TAG: u64 = 42;
+
+// A CARv1 archive: a DAG-CBOR header naming the roots, then
+// `varint(len) | cid | block` sections
+#[derive(Debug)]
+pub struct CarFile {
+    pub roots: Vec<String>,
+    pub blocks: Vec<CarBlock>,
+    // the header exactly as read, so a filtered archive keeps it byte for byte
+    header: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct CarBlock {
+    pub cid: String,
+    pub data: Vec<u8>,
+    cid_bytes: Vec<u8>,
+}
+
+impl CarFile {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let (header_len, read) = read_varint(bytes).context("Missing CAR header length")?;
+        let header_end = read
+            .checked_add(usize::try_from(header_len)?)
+            .filter(|end| *end <= bytes.len())
+            .context("CAR header is truncated")?;
+        let roots = parse_header(&bytes[read..header_end])?;
+
+        let mut blocks = Vec::new();
+        let mut rest = &bytes[header_end..];
+        while !rest.is_empty() {
+            let (section_len, read) = read_varint(rest).context("Invalid CAR section length")?;
+            let section = rest[read..]
+                .get(..usize::try_from(section_len)?)
+                .with_context(|| format!("CAR section {} is truncated", blocks.len()))?;
+            rest = &rest[read + section.len()..];
+
+            let cid_len = binary_cid_len(section)
+                .with_context(|| format!("Invalid CID in CAR section {}", blocks.len()))?;
+            let cid_bytes = section[..cid_len].to_vec();
+            blocks.push(CarBlock {
+                cid: format_binary(&cid_bytes),
+                data: section[cid_len..].to_vec(),
+                cid_bytes,
+            });
+        }
+
+        Ok(Self {
+            roots,
+            blocks,
+            header: bytes[..header_end].to_vec(),
+        })
+    }
+
+    // the archive with only the blocks `keep` accepts; roots are unchanged
+    pub fn encode_filtered(&self, keep: impl Fn(&CarBlock) -> bool) -> Vec<u8> {
+        let mut out = self.header.clone();
+        for block in self.blocks.iter().filter(|block| keep(block)) {
+            push_varint(&mut out, (block.cid_bytes.len() + block.data.len()) as u64);
+            out.extend_from_slice(&block.cid_bytes);
+            out.extend_from_slice(&block.data);
+        }
+        out
+    }
+}
+
+impl CarBlock {
+    // checks the block's bytes hash to its CID; only sha2-256 and identity
+    // hashes can be checked, anything else is an error
+    pub fn verify(&self) -> Result<()> {
+        let multihash = if is_binary_v0(&self.cid_bytes) {
+            &self.cid_bytes[..]
+        } else {
+            let (_, version_len) = read_varint(&self.cid_bytes).context("Invalid CID")?;
+            let (_, codec_len) =
+                read_varint(&self.cid_bytes[version_len..]).context("Invalid CID")?;
+            &self.cid_bytes[version_len + codec_len..]
+        };
+
+        let (code, code_len) = read_varint(multihash).context("Invalid multihash")?;
+        let (_, digest_len_len) =
+            read_varint(&multihash[code_len..]).context("Invalid multihash")?;
+        let digest = &multihash[code_len + digest_len_len..];
+
+        let matches = match code {
+            MULTIHASH_SHA2_256 => Sha256::digest(&self.data).as_slice() == digest,
+            MULTIHASH_IDENTITY => self.data == digest,
+            other => anyhow::bail!("Unsupported hash function 0x{:x}", other),
+        };
+        if !matches {
+            anyhow::bail!("Block data does not match its CID");
+        }
+        Ok(())
+    }
+}
+
+// `{ version: 1, roots: [CID] }`
+fn parse_header(bytes: &[u8]) -> Result<Vec<String>> {
+    let header: Value = ciborium::from_reader(bytes).context("CAR header is not valid CBOR")?;
+    let Value::Map(entries) = header else {
+        anyhow::bail!("CAR header is not a map");
+    };
+    let field = |name: &str| {
+        entries
+            .iter()
+            .find(|(key, _)| key.as_text() == Some(name))
+            .map(|(_, value)| value)
+    };
+
+    match field("version").and_then(Value::as_integer) {
+        Some(version) if version == 1.into() => {}
+        _ => anyhow::bail!("Only CARv1 archives are supported"),
+    }
+
+    let Some(Value::Array(roots)) = field("roots") else {
+        anyhow::bail!("CAR header has no roots");
+    };
+    roots
+        .iter()
+        .map(|root| match root {
+            // DAG-CBOR prefixes the binary CID with the identity multibase 0x00
+            Value::Tag(CID_TAG, link) => match link.as_bytes().map(Vec::as_slice) {
+                Some([0x00, cid @ ..]) if binary_cid_len(cid) == Some(cid.len()) => {
+                    Ok(format_binary(cid))
+                }
+                _ => anyhow::bail!("Invalid root CID in CAR header"),
+            },
+            _ => anyhow::bail!("CAR root is not a CID link"),
+        })
+        .collect()
+}
+
+// length of the binary CID at the start of `bytes`
+fn binary_cid_len(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() >= 34 && is_binary_v0(&bytes[..34]) {
+        return Some(34);
+    }
+
+    let (version, mut len) = read_varint(bytes)?;
+    if version != 1 {
+        return None;
+    }
+    let (_, codec_len) = read_varint(&bytes[len..])?;
+    len += codec_len;
+    let (_, code_len) = read_varint(&bytes[len..])?;
+    len += code_len;
+    let (digest_len, digest_len_len) = read_varint(&bytes[len..])?;
+    // the digest length is untrusted input, it can claim anything up to u64::MAX
+    len = len
+        .checked_add(digest_len_len)?
+        .checked_add(usize::try_from(digest_len).ok()?)?;
+
+    (len <= bytes.len()).then_some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAW_CODEC: u64 = 0x55;
+
+    fn raw_cid(data: &[u8]) -> Vec<u8> {
+        let mut cid = Vec::new();
+        push_varint(&mut cid, 1);
+        push_varint(&mut cid, RAW_CODEC);
+        push_varint(&mut cid, MULTIHASH_SHA2_256);
+        push_varint(&mut cid, 32);
+        cid.extend_from_slice(&Sha256::digest(data));
+        cid
+    }
+
+    fn header(roots: &[&[u8]]) -> Vec<u8> {
+        let roots = roots
+            .iter()
+            .map(|cid| {
+                let mut link = vec![0x00];
+                link.extend_from_slice(cid);
+                Value::Tag(CID_TAG, Box::new(Value::Bytes(link)))
+            })
+            .collect();
+        let header = Value::Map(vec![
+            (Value::Text("version".into()), Value::Integer(1.into())),
+            (Value::Text("roots".into()), Value::Array(roots)),
+        ]);
+        let mut encoded = Vec::new();
+        ciborium::into_writer(&header, &mut encoded).unwrap();
+
+        let mut out = Vec::new();
+        push_varint(&mut out, encoded.len() as u64);
+        out.extend(encoded);
+        out
+    }
+
+    fn section(out: &mut Vec<u8>, cid: &[u8], data: &[u8]) {
+        push_varint(out, (cid.len() + data.len()) as u64);
+        out.extend_from_slice(cid);
+        out.extend_from_slice(data);
+    }
+
+    fn archive(blocks: &[&[u8]]) -> Vec<u8> {
+        let cids: Vec<Vec<u8>> = blocks.iter().map(|data| raw_cid(data)).collect();
+        let mut out = header(&[&cids[0]]);
+        for (cid, data) in cids.iter().zip(blocks) {
+            section(&mut out, cid, data);
+        }
+        out
+    }
+
+    #[test]
+    fn parses_and_verifies_an_archive() {
+        let bytes = archive(&[b"hello", b"world"]);
+        let car = CarFile::parse(&bytes).unwrap();
+
+        assert_eq!(car.roots, [format_binary(&raw_cid(b"hello"))]);
+        assert_eq!(car.blocks.len(), 2);
+        assert_eq!(car.blocks[1].data, b"world");
+        for block in &car.blocks {
+            block.verify().unwrap();
+        }
+        assert_eq!(car.encode_filtered(|_| true), bytes);
+    }
+
+    #[test]
+    fn tampered_blocks_fail_verification() {
+        let mut bytes = header(&[&raw_cid(b"hello")]);
+        section(&mut bytes, &raw_cid(b"hello"), b"jello");
+        let car = CarFile::parse(&bytes).unwrap();
+        assert!(car.blocks[0].verify().is_err());
+    }
+
+    #[test]
+    fn filtering_keeps_the_header() {
+        let car = CarFile::parse(&archive(&[b"hello", b"world"])).unwrap();
+        let filtered = car.encode_filtered(|block| block.data == b"world");
+        let filtered = CarFile::parse(&filtered).unwrap();
+        assert_eq!(filtered.roots, car.roots);
+        assert_eq!(filtered.blocks.len(), 1);
+        assert_eq!(filtered.blocks[0].data, b"world");
+    }
+
+    #[test]
+    fn truncated_archives_are_rejected() {
+        let bytes = archive(&[b"hello", b"world"]);
+        for len in [0, 1, 10, bytes.len() - 1] {
+            assert!(CarFile::parse(&bytes[..len]).is_err(), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn oversized_digest_lengths_are_rejected() {
+        let mut cid = Vec::new();
+        push_varint(&mut cid, 1);
+        push_varint(&mut cid, RAW_CODEC);
+        push_varint(&mut cid, MULTIHASH_SHA2_256);
+        push_varint(&mut cid, u64::MAX);
+        cid.extend_from_slice(&[0; 32]);
+        assert_eq!(binary_cid_len(&cid), None);
+
+        let mut bytes = header(&[&raw_cid(b"hello")]);
+        section(&mut bytes, &cid, b"hello");
+        assert!(CarFile::parse(&bytes).is_err());
+
+        // a digest claiming more bytes than the section holds
+        let mut cid = raw_cid(b"hello");
+        cid[3] = 33;
+        assert_eq!(binary_cid_len(&cid), None);
+    }
+
+    #[test]
+    fn headers_must_be_carv1_with_cid_roots() {
+        let mut bytes = Vec::new();
+        let mut encoded = Vec::new();
+        let header = Value::Map(vec![
+            (Value::Text("version".into()), Value::Integer(2.into())),
+            (Value::Text("roots".into()), Value::Array(vec![])),
+        ]);
+        ciborium::into_writer(&header, &mut encoded).unwrap();
+        push_varint(&mut bytes, encoded.len() as u64);
+        bytes.extend(encoded);
+        assert!(CarFile::parse(&bytes).is_err());
+
+        let mut bytes = vec![3];
+        bytes.extend_from_slice(b"abc");
+        assert!(CarFile::parse(&bytes).is_err());
+    }
+}
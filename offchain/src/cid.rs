@@ -0,0 +1,215 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+// Kubo's default chunker (`size-262144`); anything larger spans several blocks
+pub const MAX_SINGLE_BLOCK_BYTES: usize = 256 * 1024;
+
+const CODEC_RAW: u8 = 0x55;
+const CODEC_DAG_PB: u8 = 0x70;
+const MULTIHASH_SHA2_256: u8 = 0x12;
+
+// Computes the CID `ipfs add` gives `bytes`, without contacting a node.
+//
+// Mirrors a default `add` (sha2-256, 256 KiB chunks) for content that fits
+// in a single chunk: with `raw_leaves` (CIDv1 only) the CID of the raw block,
+// otherwise that of a dag-pb node wrapping a UnixFS file. Larger content
+// becomes a multi-block DAG whose root depends on the layout, so it's
+// rejected rather than guessed.
+pub fn compute(bytes: &[u8], cid_version: u8, raw_leaves: bool) -> Result<String> {
+    if bytes.len() > MAX_SINGLE_BLOCK_BYTES {
+        anyhow::bail!(
+            "Content is {} bytes; local CIDs are only computed up to {} bytes",
+            bytes.len(),
+            MAX_SINGLE_BLOCK_BYTES
+        );
+    }
+
+    match (cid_version, raw_leaves) {
+        (0, false) => Ok(bs58_encode(&multihash(&unixfs_file_node(bytes)))),
+        (1, true) => Ok(cid_v1(CODEC_RAW, bytes)),
+        (1, false) => Ok(cid_v1(CODEC_DAG_PB, &unixfs_file_node(bytes))),
+        (0, true) => anyhow::bail!("Raw leaves require CIDv1"),
+        (version, _) => anyhow::bail!("Unsupported CID version {}", version),
+    }
+}
+
+// String form of a binary CID: base58btc for CIDv0, base32 for CIDv1
+pub fn format_binary(cid: &[u8]) -> String {
+    if is_binary_v0(cid) {
+        bs58_encode(cid)
+    } else {
+        format!("b{}", base32_lower(cid))
+    }
+}
+
+// a CIDv0 is a bare sha2-256 multihash
+pub fn is_binary_v0(cid: &[u8]) -> bool {
+    cid.len() == 34 && cid[0] == MULTIHASH_SHA2_256 && cid[1] == 32
+}
+
+fn cid_v1(codec: u8, block: &[u8]) -> String {
+    let mut cid = vec![0x01, codec];
+    cid.extend(multihash(block));
+    format!("b{}", base32_lower(&cid))
+}
+
+fn multihash(block: &[u8]) -> Vec<u8> {
+    let mut hash = vec![MULTIHASH_SHA2_256, 32];
+    hash.extend_from_slice(&Sha256::digest(block));
+    hash
+}
+
+// PBNode { Data: unixfs.Data { Type: File, Data, filesize } }, no links
+fn unixfs_file_node(bytes: &[u8]) -> Vec<u8> {
+    let mut unixfs = vec![0x08, 0x02];
+    if !bytes.is_empty() {
+        unixfs.push(0x12);
+        push_varint(&mut unixfs, bytes.len() as u64);
+        unixfs.extend_from_slice(bytes);
+    }
+    unixfs.push(0x18);
+    push_varint(&mut unixfs, bytes.len() as u64);
+
+    let mut node = vec![0x0a];
+    push_varint(&mut node, unixfs.len() as u64);
+    node.extend(unixfs);
+    node
+}
+
+pub fn push_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// (value, bytes read) of the unsigned LEB128 varint at the start of `bytes`
+pub fn read_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+// RFC 4648 base32, lowercase, unpadded (multibase `b`)
+fn base32_lower(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for &byte in bytes {
+        buffer = (buffer << 8) | u32::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+// base58btc, as used by CIDv0
+fn bs58_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+    let mut digits: Vec<u8> = Vec::new();
+    for &byte in bytes {
+        let mut carry = u32::from(byte);
+        for digit in digits.iter_mut() {
+            carry += u32::from(*digit) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let zeros = bytes.iter().take_while(|&&byte| byte == 0).count();
+    std::iter::repeat_n('1', zeros)
+        .chain(
+            digits
+                .iter()
+                .rev()
+                .map(|&digit| ALPHABET[digit as usize] as char),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // reference CIDs from `ipfs add` on a stock Kubo node
+    #[test]
+    fn matches_kubo_for_known_content() {
+        assert_eq!(
+            compute(b"hello world\n", 0, false).unwrap(),
+            "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o"
+        );
+        assert_eq!(
+            compute(b"hello world", 0, false).unwrap(),
+            "Qmf412jQZiuVUtdgnB36FXFX7xg5V6KEbSJ4dpQuhkLyfD"
+        );
+        assert_eq!(
+            compute(b"", 0, false).unwrap(),
+            "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH"
+        );
+        assert_eq!(
+            compute(b"hello world\n", 1, true).unwrap(),
+            "bafkreifjjcie6lypi6ny7amxnfftagclbuxndqonfipmb64f2km2devei4"
+        );
+    }
+
+    #[test]
+    fn cidv1_dag_pb_wraps_the_same_node_as_cidv0() {
+        let v1 = compute(b"hello world\n", 1, false).unwrap();
+        assert!(v1.starts_with("bafybei"));
+        let node = unixfs_file_node(b"hello world\n");
+        assert_eq!(v1, cid_v1(CODEC_DAG_PB, &node));
+        assert_eq!(
+            bs58_encode(&multihash(&node)),
+            compute(b"hello world\n", 0, false).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_compute() {
+        assert!(compute(&vec![0; MAX_SINGLE_BLOCK_BYTES], 0, false).is_ok());
+        assert!(compute(&vec![0; MAX_SINGLE_BLOCK_BYTES + 1], 0, false).is_err());
+        assert!(compute(b"data", 0, true).is_err());
+        assert!(compute(b"data", 2, false).is_err());
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for value in [0, 1, 127, 128, 300, 262_144, u64::MAX] {
+            let mut bytes = Vec::new();
+            push_varint(&mut bytes, value);
+            assert_eq!(read_varint(&bytes), Some((value, bytes.len())));
+        }
+        assert_eq!(read_varint(&[0x80]), None);
+        assert_eq!(read_varint(&[]), None);
+    }
+
+    #[test]
+    fn binary_cids_format_by_version() {
+        let v0 = multihash(b"block");
+        assert!(is_binary_v0(&v0));
+        assert!(format_binary(&v0).starts_with("Qm"));
+
+        let mut v1 = vec![0x01, CODEC_RAW];
+        v1.extend(multihash(b"block"));
+        assert!(!is_binary_v0(&v1));
+        assert_eq!(format_binary(&v1), cid_v1(CODEC_RAW, b"block"));
+    }
+}
@@ -1,10 +1,20 @@
-use std::env;
+use anyhow::Context;
+use std::{collections::HashMap, env, time::Duration};
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub port: u16,
     pub host: String,
     pub environment: Environment,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    // longest a request may take, per route
+    pub handler_timeouts: HandlerTimeouts,
+    // log JSON request bodies (redacted); only when the policy allows debugging aids
+    pub log_bodies: bool,
+    pub safety: SafetyPolicy,
+    // origins allowed cross-origin access when CORS isn't permissive
+    pub cors_allowed_origins: Vec<String>,
     // pub ipfs_api_url: String,
     // pub ipfs_project_id: String,
     // pub ipfs_project_secret: String,
@@ -13,6 +23,17 @@ pub struct Config {
     // pub db_url: String,
 }
 
+const DEFAULT_HANDLER_TIMEOUT: Duration = Duration::from_secs(30);
+
+// bulk uploads and dead-letter replays legitimately run for minutes, so they
+// have no budget unless configured; a replay cut short would re-send entries
+// it had already delivered
+const UNBOUNDED_ROUTES: [&str; 3] = [
+    "/api/v1/admin/import.car",
+    "/api/v1/ipfs/add-dir",
+    "/api/v1/admin/dead-letter/replay",
+];
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Environment {
     Development,
@@ -21,6 +42,7 @@ pub enum Environment {
 }
 
 impl Environment {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
         match s.to_lowercase().as_str() {
             "production" | "prod" => Environment::Production,
@@ -29,6 +51,13 @@ impl Environment {
         }
     }
 
+    //  ENVIRONMENT, development when unset
+    pub fn from_env() -> Self {
+        env::var("ENVIRONMENT")
+            .map(|e| Environment::from_str(&e))
+            .unwrap_or(Environment::Development)
+    }
+
     pub fn is_production(&self) -> bool {
         matches!(self, Environment::Production)
     }
@@ -50,17 +79,56 @@ impl Config {
 
         let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
 
-        let environment = env::var("ENVIRONMENT")
-            .map(|e| Environment::from_str(&e))
-            .unwrap_or(Environment::Development);
+        let environment = Environment::from_env();
+        let safety = SafetyPolicy::from_env(&environment)?;
+
+        let tls_cert = env::var("TLS_CERT").ok().filter(|path| !path.is_empty());
+        let tls_key = env::var("TLS_KEY").ok().filter(|path| !path.is_empty());
+
+        if tls_cert.is_some() != tls_key.is_some() {
+            anyhow::bail!("TLS_CERT and TLS_KEY must be set together to enable TLS");
+        }
+
+        let handler_timeouts = HandlerTimeouts::from_env()?;
+
+        let log_bodies = safety.allows_body_logging(
+            env::var("LOG_BODIES")
+                .map(|v| matches!(v.to_lowercase().as_str(), "true" | "1" | "yes"))
+                .unwrap_or(false),
+        );
+
+        let cors_allowed_origins = env::var("CORS_ALLOWED_ORIGINS")
+            .map(|origins| {
+                origins
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|origin| !origin.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
 
         Ok(Config {
             port,
             host,
             environment,
+            tls_cert,
+            tls_key,
+            handler_timeouts,
+            log_bodies,
+            safety,
+            cors_allowed_origins,
         })
     }
 
+    // (cert, key) PEM paths when the server should terminate TLS itself
+    pub fn tls_paths(&self) -> Option<(&str, &str)> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            _ => None,
+        }
+    }
+
     pub fn address(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
@@ -72,6 +140,329 @@ impl Default for Config {
             port: 3000,
             host: "0.0.0.0".to_string(),
             environment: Environment::Development,
+            tls_cert: None,
+            tls_key: None,
+            handler_timeouts: HandlerTimeouts::new(Some(DEFAULT_HANDLER_TIMEOUT)),
+            log_bodies: false,
+            safety: SafetyPolicy::for_environment(&Environment::Development),
+            cors_allowed_origins: Vec::new(),
+        }
+    }
+}
+
+// How long a request may take, by route; `None` means no limit.
+//
+// Routes are keyed as registered (`/api/v1/ipfs/cat/:cid`); requests that
+// match no route get the default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandlerTimeouts {
+    default: Option<Duration>,
+    routes: HashMap<String, Option<Duration>>,
+}
+
+impl HandlerTimeouts {
+    // `default` everywhere but the bulk upload routes
+    pub fn new(default: Option<Duration>) -> Self {
+        let routes = UNBOUNDED_ROUTES
+            .iter()
+            .map(|route| (route.to_string(), None))
+            .collect();
+        Self { default, routes }
+    }
+
+    pub fn with_route(mut self, route: &str, budget: Option<Duration>) -> Self {
+        self.routes.insert(route.to_string(), budget);
+        self
+    }
+
+    pub fn for_route(&self, route: Option<&str>) -> Option<Duration> {
+        route
+            .and_then(|route| self.routes.get(route))
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    //  HANDLER_TIMEOUT_MS, HANDLER_TIMEOUT_ROUTES
+    pub fn from_env() -> anyhow::Result<Self> {
+        let default = match env::var("HANDLER_TIMEOUT_MS") {
+            Ok(ms) => parse_timeout_ms(&ms)?,
+            Err(_) => Some(DEFAULT_HANDLER_TIMEOUT),
+        };
+        let routes = env::var("HANDLER_TIMEOUT_ROUTES").unwrap_or_default();
+        Self::new(default).with_routes(&routes)
+    }
+
+    // `route=ms` pairs, comma-separated, e.g.
+    // `/api/v1/ipfs/cat/:cid=120000,/api/v1/admin/import.car=600000`
+    pub fn with_routes(self, spec: &str) -> anyhow::Result<Self> {
+        spec.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .try_fold(self, |timeouts, entry| {
+                let (route, ms) = entry.rsplit_once('=').with_context(|| {
+                    format!("HANDLER_TIMEOUT_ROUTES entry '{}' is not route=ms", entry)
+                })?;
+                Ok(timeouts.with_route(route.trim(), parse_timeout_ms(ms)?))
+            })
+    }
+}
+
+// milliseconds, where 0 turns the limit off
+fn parse_timeout_ms(ms: &str) -> anyhow::Result<Option<Duration>> {
+    let ms = ms
+        .trim()
+        .parse::<u64>()
+        .with_context(|| format!("Invalid timeout '{}', expected milliseconds", ms))?;
+    Ok(Some(ms).filter(|ms| *ms > 0).map(Duration::from_millis))
+}
+
+// Startup defaults that follow the environment: strict in production,
+// relaxed everywhere else. Each setting can be overridden by its own
+// variable, so production can opt out of one check without relaxing all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafetyPolicy {
+    // refuse to start without ADMIN_API_TOKEN  (REQUIRE_ADMIN_TOKEN)
+    pub require_admin_token: bool,
+    // any origin may call the API; otherwise only CORS_ALLOWED_ORIGINS  (CORS_PERMISSIVE)
+    pub permissive_cors: bool,
+    // refuse to talk to an IPFS node without credentials  (REQUIRE_IPFS_AUTH)
+    pub require_ipfs_auth: bool,
+    // one JSON object per log line  (LOG_FORMAT=json|text)
+    pub json_logs: bool,
+    // debugging aids such as LOG_BODIES  (ALLOW_DEBUG)
+    pub allow_debug: bool,
+}
+
+impl SafetyPolicy {
+    pub fn for_environment(environment: &Environment) -> Self {
+        let strict = environment.is_production();
+        Self {
+            require_admin_token: strict,
+            permissive_cors: !strict,
+            require_ipfs_auth: strict,
+            json_logs: strict,
+            allow_debug: !strict,
+        }
+    }
+
+    // the environment's defaults with any explicit overrides applied
+    pub fn from_env(environment: &Environment) -> anyhow::Result<Self> {
+        Self::from_lookup(environment, |name| env::var(name).ok())
+    }
+
+    // `from_env` with the variables read through `lookup`
+    fn from_lookup(
+        environment: &Environment,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<Self> {
+        let flag = |name: &str| parse_flag(name, lookup(name));
+        let mut policy = Self::for_environment(environment);
+
+        if let Some(require) = flag("REQUIRE_ADMIN_TOKEN")? {
+            policy.require_admin_token = require;
         }
+        // listing origins is itself a choice against permissive CORS
+        if lookup("CORS_ALLOWED_ORIGINS").is_some_and(|origins| !origins.trim().is_empty()) {
+            policy.permissive_cors = false;
+        }
+        if let Some(permissive) = flag("CORS_PERMISSIVE")? {
+            policy.permissive_cors = permissive;
+        }
+        if let Some(require) = flag("REQUIRE_IPFS_AUTH")? {
+            policy.require_ipfs_auth = require;
+        }
+        if let Some(format) = lookup("LOG_FORMAT") {
+            policy.json_logs = match format.to_lowercase().as_str() {
+                "json" => true,
+                "text" | "pretty" => false,
+                other => anyhow::bail!("LOG_FORMAT must be json or text, got '{}'", other),
+            };
+        }
+        if let Some(allow) = flag("ALLOW_DEBUG")? {
+            policy.allow_debug = allow;
+        }
+
+        Ok(policy)
+    }
+
+    pub fn check_admin_token(&self, configured: bool) -> anyhow::Result<()> {
+        if self.require_admin_token && !configured {
+            anyhow::bail!(
+                "ADMIN_API_TOKEN is required in this environment (set REQUIRE_ADMIN_TOKEN=false to start without it)"
+            );
+        }
+        Ok(())
+    }
+
+    // whether request bodies get logged when LOG_BODIES asks for it
+    pub fn allows_body_logging(&self, requested: bool) -> bool {
+        if requested && !self.allow_debug {
+            tracing::warn!("LOG_BODIES is ignored while debugging aids are disabled (ALLOW_DEBUG)");
+            return false;
+        }
+        requested
+    }
+
+    pub fn check_ipfs_auth(&self, configured: bool) -> anyhow::Result<()> {
+        if self.require_ipfs_auth && !configured {
+            anyhow::bail!(
+                "IPFS credentials are required in this environment (set IPFS_PROJECT_ID/IPFS_PROJECT_SECRET or IPFS_BEARER_TOKEN, or REQUIRE_IPFS_AUTH=false)"
+            );
+        }
+        Ok(())
+    }
+}
+
+// true/false, 1/0 or yes/no; `None` when unset
+fn parse_flag(name: &str, value: Option<String>) -> anyhow::Result<Option<bool>> {
+    match value {
+        Some(value) => match value.to_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(Some(true)),
+            "false" | "0" | "no" => Ok(Some(false)),
+            _ => anyhow::bail!("{} must be true or false, got '{}'", name, value),
+        },
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bulk_routes_are_unbounded_by_default() {
+        let timeouts = HandlerTimeouts::new(Some(DEFAULT_HANDLER_TIMEOUT));
+        assert_eq!(
+            timeouts.for_route(Some("/api/v1/ipfs/cat/:cid")),
+            Some(DEFAULT_HANDLER_TIMEOUT)
+        );
+        assert_eq!(timeouts.for_route(None), Some(DEFAULT_HANDLER_TIMEOUT));
+        assert_eq!(timeouts.for_route(Some("/api/v1/admin/import.car")), None);
+        assert_eq!(timeouts.for_route(Some("/api/v1/ipfs/add-dir")), None);
+        assert_eq!(
+            timeouts.for_route(Some("/api/v1/admin/dead-letter/replay")),
+            None
+        );
+    }
+
+    #[test]
+    fn route_budgets_are_parsed_from_pairs() {
+        let timeouts = HandlerTimeouts::new(None)
+            .with_routes(" /api/v1/ipfs/cat/:cid=1500, /api/v1/ipfs/add-dir=0,")
+            .unwrap();
+        assert_eq!(
+            timeouts.for_route(Some("/api/v1/ipfs/cat/:cid")),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(timeouts.for_route(Some("/api/v1/ipfs/add-dir")), None);
+        assert_eq!(timeouts.for_route(Some("/health")), None);
+
+        assert!(HandlerTimeouts::new(None).with_routes("/health").is_err());
+        assert!(HandlerTimeouts::new(None)
+            .with_routes("/health=soon")
+            .is_err());
+    }
+
+    fn policy(environment: Environment, vars: &[(&str, &str)]) -> anyhow::Result<SafetyPolicy> {
+        SafetyPolicy::from_lookup(&environment, |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn production_is_strict_and_everything_else_relaxed() {
+        let production = policy(Environment::Production, &[]).unwrap();
+        assert!(production.require_admin_token);
+        assert!(production.require_ipfs_auth);
+        assert!(production.json_logs);
+        assert!(!production.permissive_cors);
+        assert!(!production.allow_debug);
+
+        for environment in [Environment::Development, Environment::Test] {
+            let relaxed = policy(environment, &[]).unwrap();
+            assert!(!relaxed.require_admin_token);
+            assert!(!relaxed.require_ipfs_auth);
+            assert!(!relaxed.json_logs);
+            assert!(relaxed.permissive_cors);
+            assert!(relaxed.allow_debug);
+        }
+    }
+
+    #[test]
+    fn each_setting_can_be_overridden() {
+        let production = policy(
+            Environment::Production,
+            &[
+                ("REQUIRE_ADMIN_TOKEN", "false"),
+                ("REQUIRE_IPFS_AUTH", "0"),
+                ("LOG_FORMAT", "text"),
+                ("ALLOW_DEBUG", "yes"),
+                ("CORS_PERMISSIVE", "TRUE"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(production, policy(Environment::Development, &[]).unwrap());
+
+        let development =
+            policy(Environment::Development, &[("REQUIRE_ADMIN_TOKEN", "1")]).unwrap();
+        assert!(development.require_admin_token);
+        assert!(!development.require_ipfs_auth);
+    }
+
+    #[test]
+    fn bodies_are_never_logged_in_production() {
+        let production = policy(Environment::Production, &[]).unwrap();
+        assert!(!production.allows_body_logging(true));
+
+        let development = policy(Environment::Development, &[]).unwrap();
+        assert!(development.allows_body_logging(true));
+        assert!(!development.allows_body_logging(false));
+
+        // unless debugging aids are explicitly allowed there
+        let debugging = policy(Environment::Production, &[("ALLOW_DEBUG", "true")]).unwrap();
+        assert!(debugging.allows_body_logging(true));
+    }
+
+    #[test]
+    fn listing_origins_turns_off_permissive_cors() {
+        let listed = [("CORS_ALLOWED_ORIGINS", "https://app.example")];
+        assert!(
+            !policy(Environment::Development, &listed)
+                .unwrap()
+                .permissive_cors
+        );
+        assert!(
+            policy(Environment::Development, &[("CORS_ALLOWED_ORIGINS", " ")])
+                .unwrap()
+                .permissive_cors
+        );
+        // an explicit flag still wins
+        let forced = [listed[0], ("CORS_PERMISSIVE", "true")];
+        assert!(
+            policy(Environment::Development, &forced)
+                .unwrap()
+                .permissive_cors
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_errors() {
+        assert!(policy(Environment::Production, &[("ALLOW_DEBUG", "maybe")]).is_err());
+        assert!(policy(Environment::Production, &[("LOG_FORMAT", "xml")]).is_err());
+    }
+
+    #[test]
+    fn checks_only_fail_when_required_and_missing() {
+        let strict = SafetyPolicy::for_environment(&Environment::Production);
+        assert!(strict.check_admin_token(false).is_err());
+        assert!(strict.check_admin_token(true).is_ok());
+        assert!(strict.check_ipfs_auth(false).is_err());
+        assert!(strict.check_ipfs_auth(true).is_ok());
+
+        let relaxed = SafetyPolicy::for_environment(&Environment::Development);
+        assert!(relaxed.check_admin_token(false).is_ok());
+        assert!(relaxed.check_ipfs_auth(false).is_ok());
     }
 }
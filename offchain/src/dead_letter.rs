@@ -0,0 +1,186 @@
+use crate::ipfs::IpfsClient;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    env,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+
+// An upload the node refused, kept so it can be replayed later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    // unix seconds
+    pub failed_at: u64,
+    // CID the upload would have had, computed locally when possible
+    pub cid: Option<String>,
+    // exactly what was to be uploaded, after PII encryption
+    pub data: Value,
+    pub pin: bool,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReplayReport {
+    pub replayed: Vec<String>,
+    pub failed: usize,
+    pub remaining: usize,
+}
+
+// Append-only JSON-lines file of failed uploads.
+//
+// Replay re-uploads a snapshot of the entries, then swaps in a file holding
+// those that still fail plus anything recorded in the meantime; failures keep
+// being recorded while the uploads run. Entries carrying the same CID are
+// uploaded once, and since uploads of pinned content are deduplicated a
+// replay never stores anything twice.
+#[derive(Debug)]
+pub struct DeadLetterStore {
+    path: PathBuf,
+    // serialises appends against replay's snapshot and swap
+    lock: Mutex<()>,
+    // one replay at a time
+    replaying: Mutex<()>,
+}
+
+impl DeadLetterStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+            replaying: Mutex::new(()),
+        }
+    }
+
+    //  DEAD_LETTER_PATH
+    pub fn from_env() -> Option<Self> {
+        env::var("DEAD_LETTER_PATH")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(Self::new)
+    }
+
+    // Records a failed upload; failures to write are logged, never returned,
+    // so the caller still reports the original error.
+    pub async fn record(&self, client: &IpfsClient, data: Value, pin: bool, error: &anyhow::Error) {
+        let letter = DeadLetter {
+            failed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            cid: client.compute_cid_json(&data).ok(),
+            data,
+            pin,
+            error: error.to_string(),
+        };
+
+        match self.append(&letter).await {
+            Ok(()) => {
+                tracing::warn!(cid = ?letter.cid, "Failed upload written to dead-letter store")
+            }
+            Err(e) => tracing::error!(error = %e, "Failed to write dead letter, upload data lost"),
+        }
+    }
+
+    async fn append(&self, letter: &DeadLetter) -> Result<()> {
+        let mut line = serde_json::to_vec(letter)?;
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().await;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    pub async fn replay(&self, client: &IpfsClient) -> Result<ReplayReport> {
+        let _replaying = self.replaying.lock().await;
+        let snapshot = {
+            let _guard = self.lock.lock().await;
+            self.read().await?
+        };
+
+        let mut report = ReplayReport::default();
+        let mut done: HashSet<String> = HashSet::new();
+        let mut still_failing = Vec::new();
+
+        for letter in parse(&snapshot)? {
+            if letter.cid.as_ref().is_some_and(|cid| done.contains(cid)) {
+                continue;
+            }
+
+            match client.upload_json(&letter.data, letter.pin).await {
+                Ok(result) => {
+                    tracing::info!(cid = %result.cid, "Replayed dead-lettered upload");
+                    done.insert(result.cid.clone());
+                    if let Some(cid) = letter.cid {
+                        done.insert(cid);
+                    }
+                    report.replayed.push(result.cid);
+                }
+                Err(e) => {
+                    tracing::warn!(cid = ?letter.cid, error = %e, "Dead-lettered upload failed again");
+                    report.failed += 1;
+                    still_failing.push(DeadLetter {
+                        error: e.to_string(),
+                        ..letter
+                    });
+                }
+            }
+        }
+
+        let _guard = self.lock.lock().await;
+        // only appends touch the file between the snapshot and here
+        let current = self.read().await?;
+        let appended = current.get(snapshot.len()..).unwrap_or_default();
+
+        let mut contents = Vec::new();
+        for letter in &still_failing {
+            contents.extend(serde_json::to_vec(letter)?);
+            contents.push(b'\n');
+        }
+        contents.extend_from_slice(appended.as_bytes());
+
+        // a crash mid-write leaves the old file in place rather than a truncated one
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, contents)
+            .await
+            .with_context(|| format!("Failed to write {}", PathBuf::from(&temp_path).display()))?;
+        fs::rename(&temp_path, &self.path)
+            .await
+            .with_context(|| format!("Failed to replace {}", self.path.display()))?;
+
+        report.remaining = still_failing.len() + parse(appended)?.len();
+        Ok(report)
+    }
+
+    // the whole file, empty when it doesn't exist yet
+    async fn read(&self) -> Result<String> {
+        match fs::read_to_string(&self.path).await {
+            Ok(contents) => Ok(contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", self.path.display())),
+        }
+    }
+
+    pub async fn load(&self) -> Result<Vec<DeadLetter>> {
+        parse(&self.read().await?)
+    }
+}
+
+fn parse(contents: &str) -> Result<Vec<DeadLetter>> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).context("Corrupt dead-letter entry"))
+        .collect()
+}
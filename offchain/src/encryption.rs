@@ -0,0 +1,203 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{env, fmt};
+
+pub const ENCRYPTION_SCHEME: &str = "aes-256-gcm";
+// object key recording which fields are ciphertext
+pub const ENCRYPTION_FIELD: &str = "_encryption";
+
+const NONCE_LEN: usize = 12;
+const DEFAULT_ENCRYPTED_FIELDS: &str =
+    "registration_data.phone_number,registration_data.email,registration_data.kyc_document_url";
+
+// AES-256-GCM under one key, each message sealed as `nonce || ciphertext`
+// with a fresh random nonce.
+//
+// Two independent keys use it: PII_ENCRYPTION_KEY for `FieldEncryptor`, which
+// hides selected fields and leaves the rest of the object readable, and
+// IPFS_ENCRYPTION_KEY for the pipeline's `encrypt` transform, which hides the
+// whole stored object. They protect different things and can be rotated
+// separately, so one never stands in for the other.
+#[derive(Clone)]
+pub struct SealingKey(Aes256Gcm);
+
+impl SealingKey {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)))
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow::anyhow!("AES-GCM encryption failed"))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    // fails when `sealed` is truncated or wasn't sealed under this key
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() <= NONCE_LEN {
+            anyhow::bail!("Sealed data is truncated");
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("AES-GCM authentication failed"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptionInfo {
+    pub scheme: String,
+    // dotted paths whose values were replaced by ciphertext
+    pub fields: Vec<String>,
+}
+
+// Encrypts selected PII fields of a JSON object before upload.
+//
+// Each listed path present in the object is replaced by base64 of its JSON
+// value sealed with `SealingKey`, the rest stays in clear so it can still
+// be hashed and verified. The encrypted paths are recorded under
+// `_encryption` so `decrypt` knows what to undo.
+#[derive(Clone)]
+pub struct FieldEncryptor {
+    key: SealingKey,
+    fields: Vec<String>,
+}
+
+impl FieldEncryptor {
+    pub fn new(key: &[u8; 32], fields: Vec<String>) -> Self {
+        Self {
+            key: SealingKey::new(key),
+            fields,
+        }
+    }
+
+    //  PII_ENCRYPTION_KEY (hex, 32 bytes), PII_ENCRYPTED_FIELDS
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(key) = key_from_env("PII_ENCRYPTION_KEY")? else {
+            return Ok(None);
+        };
+
+        let fields = env::var("PII_ENCRYPTED_FIELDS")
+            .unwrap_or_else(|_| DEFAULT_ENCRYPTED_FIELDS.to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        if let Some(path) = fields
+            .iter()
+            .find(|path| path.split('.').any(str::is_empty))
+        {
+            anyhow::bail!("Invalid path in PII_ENCRYPTED_FIELDS: '{}'", path);
+        }
+
+        Ok(Some(Self::new(&key, fields)))
+    }
+
+    // encrypts the configured paths that exist in `value`; other values pass through
+    pub fn encrypt(&self, mut value: Value) -> Result<Value> {
+        let Value::Object(_) = value else {
+            return Ok(value);
+        };
+        if value.get(ENCRYPTION_FIELD).is_some() {
+            anyhow::bail!("Object already has an '{}' field", ENCRYPTION_FIELD);
+        }
+
+        let mut encrypted = Vec::new();
+        for path in &self.fields {
+            let Some(field) = lookup_mut(&mut value, path) else {
+                continue;
+            };
+
+            let sealed = self
+                .key
+                .seal(&serde_json::to_vec(field)?)
+                .with_context(|| format!("Failed to encrypt field '{}'", path))?;
+            *field = Value::String(BASE64.encode(sealed));
+            encrypted.push(path.clone());
+        }
+
+        if !encrypted.is_empty() {
+            value[ENCRYPTION_FIELD] = serde_json::to_value(EncryptionInfo {
+                scheme: ENCRYPTION_SCHEME.to_string(),
+                fields: encrypted,
+            })?;
+        }
+
+        Ok(value)
+    }
+
+    // restores the fields listed under `_encryption`; objects without it are returned as is
+    pub fn decrypt(&self, mut value: Value) -> Result<Value> {
+        let Some(info) = value
+            .as_object_mut()
+            .and_then(|object| object.remove(ENCRYPTION_FIELD))
+        else {
+            return Ok(value);
+        };
+
+        let info: EncryptionInfo =
+            serde_json::from_value(info).context("Invalid encryption metadata")?;
+        if info.scheme != ENCRYPTION_SCHEME {
+            anyhow::bail!("Unsupported encryption scheme '{}'", info.scheme);
+        }
+
+        for path in &info.fields {
+            let field = lookup_mut(&mut value, path)
+                .with_context(|| format!("Encrypted field '{}' is missing", path))?;
+
+            let sealed = field
+                .as_str()
+                .and_then(|encoded| BASE64.decode(encoded).ok())
+                .with_context(|| format!("Encrypted field '{}' is malformed", path))?;
+
+            let plaintext = self
+                .key
+                .open(&sealed)
+                .with_context(|| format!("Failed to decrypt field '{}'", path))?;
+
+            *field = serde_json::from_slice(&plaintext)?;
+        }
+
+        Ok(value)
+    }
+}
+
+impl fmt::Debug for FieldEncryptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldEncryptor")
+            .field("fields", &self.fields)
+            .finish_non_exhaustive()
+    }
+}
+
+fn lookup_mut<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.')
+        .try_fold(value, |current, segment| current.get_mut(segment))
+}
+
+// a hex-encoded AES-256 key from `var`; `None` when unset or empty
+pub fn key_from_env(var: &str) -> Result<Option<[u8; 32]>> {
+    let Some(key_hex) = env::var(var).ok().filter(|key| !key.is_empty()) else {
+        return Ok(None);
+    };
+
+    let key = hex::decode(key_hex.trim())
+        .with_context(|| format!("{} must be hex", var))?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("{} must be 32 bytes", var))?;
+    Ok(Some(key))
+}
@@ -1,8 +1,11 @@
 use axum::{
-    http::StatusCode,
+    async_trait,
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    http::{header::LOCATION, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::json;
 
 #[derive(Debug, thiserror::Error)]
@@ -13,6 +16,9 @@ pub enum AppError {
     #[error("Bad request: {0}")]
     BadRequest(String),
 
+    #[error("Unprocessable entity: {0}")]
+    Unprocessable(String),
+
     #[error("Not found: {0}")]
     NotFound(String),
 
@@ -22,18 +28,69 @@ pub enum AppError {
     #[error("Forbidden: {0}")]
     Forbidden(String),
 
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
+    #[error("Unsupported media type: {0}")]
+    UnsupportedMediaType(String),
+
+    // `location` points at the resource that already exists, sent both as the
+    // `Location` header and in the body
+    #[error("Conflict: {message}")]
+    Conflict {
+        message: String,
+        location: Option<String>,
+    },
+
+    // the IPFS node failed; `context` says which call and object
+    #[error("IPFS error: {message}")]
+    Ipfs {
+        message: String,
+        context: IpfsErrorContext,
+    },
+
+    #[error("Gateway timeout: {0}")]
+    GatewayTimeout(String),
+
     #[error(transparent)]
     Anyhow(#[from] anyhow::Error),
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct IpfsErrorContext {
+    pub op: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cid: Option<String>,
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let mut location = None;
+        let mut context = None;
         let (status, error_message) = match self {
             AppError::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
+            AppError::Unprocessable(msg) => (StatusCode::UNPROCESSABLE_ENTITY, msg),
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
             AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
             AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg),
+            AppError::PayloadTooLarge(msg) => (StatusCode::PAYLOAD_TOO_LARGE, msg),
+            AppError::UnsupportedMediaType(msg) => (StatusCode::UNSUPPORTED_MEDIA_TYPE, msg),
+            AppError::Ipfs {
+                message,
+                context: ipfs_context,
+            } => {
+                context = Some(ipfs_context);
+                (StatusCode::BAD_GATEWAY, message)
+            }
+            AppError::GatewayTimeout(msg) => (StatusCode::GATEWAY_TIMEOUT, msg),
+            AppError::Conflict {
+                message,
+                location: existing,
+            } => {
+                location = existing;
+                (StatusCode::CONFLICT, message)
+            }
             AppError::Anyhow(err) => {
                 tracing::error!("Internal error: {:?}", err);
                 (
@@ -43,12 +100,178 @@ impl IntoResponse for AppError {
             }
         };
 
-        let body = Json(json!({
+        let mut body = json!({
             "error": error_message,
-        }));
+        });
+        if let Some(location) = &location {
+            body["location"] = json!(location);
+        }
+        if let Some(context) = context {
+            body["context"] = json!(context);
+        }
+        let mut response = (status, Json(body)).into_response();
+
+        if let Some(location) = location.and_then(|l| HeaderValue::from_str(&l).ok()) {
+            response.headers_mut().insert(LOCATION, location);
+        }
+        response
+    }
+}
+
+impl AppError {
+    // Wraps a failed IpfsClient call made for `op` on `cid`.
+    //
+    // Only the outermost error message reaches the client: it never carries
+    // the node URL or credentials, the full chain goes to the log instead.
+    pub fn ipfs(op: &'static str, cid: Option<&str>, err: anyhow::Error) -> Self {
+        tracing::error!(op, cid, "IPFS {} failed: {:#}", op, err);
+        AppError::Ipfs {
+            message: err.to_string(),
+            context: IpfsErrorContext {
+                op,
+                cid: cid.map(str::to_string),
+            },
+        }
+    }
+}
+
+// well-formed JSON that doesn't fit the schema is 422, malformed JSON is 400;
+// a missing content type (415) or oversized body (413) keeps axum's status
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::JsonDataError(err) => AppError::Unprocessable(err.body_text()),
+            other => match other.status() {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE => {
+                    AppError::UnsupportedMediaType(other.body_text())
+                }
+                StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge(other.body_text()),
+                _ => AppError::BadRequest(other.body_text()),
+            },
+        }
+    }
+}
+
+// `Json` request body whose rejections are answered as `AppError`s
+#[derive(Debug)]
+pub struct JsonBody<T>(pub T);
 
-        (status, body).into_response()
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> std::result::Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        Ok(JsonBody(value))
     }
 }
 
 pub type Result<T> = std::result::Result<T, AppError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{to_bytes, Body},
+        http::header::CONTENT_TYPE,
+    };
+    use serde::Deserialize;
+    use tower::ServiceExt;
+
+    #[derive(Debug, Deserialize)]
+    struct Payload {
+        count: u32,
+    }
+
+    async fn extract(content_type: Option<&str>, body: impl Into<Body>) -> StatusCode {
+        let mut request = Request::builder().method("POST").uri("/");
+        if let Some(content_type) = content_type {
+            request = request.header(CONTENT_TYPE, content_type);
+        }
+        let request = request.body(body.into()).unwrap();
+
+        match JsonBody::<Payload>::from_request(request, &()).await {
+            Ok(JsonBody(payload)) => {
+                assert_eq!(payload.count, 3);
+                StatusCode::OK
+            }
+            Err(e) => e.into_response().status(),
+        }
+    }
+
+    #[tokio::test]
+    async fn json_body_statuses() {
+        let json = Some("application/json");
+        assert_eq!(extract(json, r#"{"count": 3}"#).await, StatusCode::OK);
+        assert_eq!(
+            extract(json, r#"{"count": 3"#).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            extract(json, r#"{"count": "three"}"#).await,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            extract(None, r#"{"count": 3}"#).await,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        assert_eq!(
+            extract(json, vec![b' '; 3 * 1024 * 1024]).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[tokio::test]
+    async fn conflicts_are_409_pointing_at_the_existing_resource() {
+        let response = AppError::Conflict {
+            message: "Object already exists".to_string(),
+            location: Some("/api/v1/ipfs/dag/QmExisting".to_string()),
+        }
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(response.headers()[LOCATION], "/api/v1/ipfs/dag/QmExisting");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "Object already exists");
+        assert_eq!(body["location"], "/api/v1/ipfs/dag/QmExisting");
+
+        let response = AppError::Conflict {
+            message: "Object already exists".to_string(),
+            location: None,
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(!response.headers().contains_key(LOCATION));
+    }
+
+    #[tokio::test]
+    async fn geo_distance_validation_is_422_and_malformed_json_is_400() {
+        let post = |body: &'static str| {
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/geo/distance")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let out_of_range = crate::routes::configure_routes()
+            .oneshot(post(
+                r#"{"from": {"latitude": 91.0, "longitude": 0.0}, "to": {"latitude": 0.0, "longitude": 0.0}}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(out_of_range.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let malformed = crate::routes::configure_routes()
+            .oneshot(post(r#"{"from": "#))
+            .await
+            .unwrap();
+        assert_eq!(malformed.status(), StatusCode::BAD_REQUEST);
+    }
+}
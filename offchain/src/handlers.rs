@@ -1,6 +1,6 @@
 use crate::{
-    error::Result,
-    models::{ApiResponse, ExampleRequest, ExampleResponse},
+    error::{AppError, JsonBody, Result},
+    models::{ApiResponse, DistanceRequest, DistanceResponse, ExampleRequest, ExampleResponse},
 };
 use axum::{extract::Path, Json};
 
@@ -11,7 +11,7 @@ pub async fn get_example(Path(id): Path<String>) -> Result<Json<ApiResponse<Stri
 
 // post
 pub async fn post_example(
-    Json(payload): Json<ExampleRequest>,
+    JsonBody(payload): JsonBody<ExampleRequest>,
 ) -> Result<Json<ApiResponse<ExampleResponse>>> {
     let response = ExampleResponse {
         success: true,
@@ -27,3 +27,18 @@ pub async fn error_example() -> Result<Json<ApiResponse<String>>> {
         "This is an example error".to_string(),
     ))
 }
+
+// geo distance
+pub async fn geo_distance(
+    JsonBody(payload): JsonBody<DistanceRequest>,
+) -> Result<Json<ApiResponse<DistanceResponse>>> {
+    if !payload.from.is_valid() || !payload.to.is_valid() {
+        return Err(AppError::Unprocessable(
+            "latitude must be within [-90, 90] and longitude within [-180, 180]".to_string(),
+        ));
+    }
+
+    let distance_meters = payload.from.distance_to(&payload.to);
+
+    Ok(Json(ApiResponse::new(DistanceResponse { distance_meters })))
+}
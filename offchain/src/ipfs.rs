@@ -1,62 +1,783 @@
+use crate::{compression::Compression, transform::Pipeline};
 use anyhow::{Context, Result};
 use reqwest::multipart::{Form, Part};
 use serde::{Deserialize, Serialize};
-use std::env;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::sync::{OnceCell, OwnedSemaphorePermit, Semaphore};
 
 #[derive(Debug, Clone)]
 pub struct IpfsConfig {
     pub api_url: String,
-    pub project_id: Option<String>,
-    pub project_secret: Option<String>,
+    // e.g. `/ipfs-api` when the API sits behind a proxy path, empty otherwise
+    pub api_path_prefix: String,
+    // default for uploads that don't say whether to pin
+    pub pin_on_upload: bool,
+    // transforms applied before upload (compression, encryption, ..), undone on reads
+    pub pipeline: Pipeline,
+    // uploads sent to the node at once, the rest wait their turn
+    pub upload_concurrency: usize,
+    // queued uploads past which `/ready` reports the service degraded
+    pub degrade_queue_depth: Option<usize>,
+    // how long a DNSLink resolution is reused before asking the node again
+    pub dnslink_ttl: Duration,
+    // remote pinning service (as named in the node's `Pinning.RemoteServices`)
+    pub remote_pin_service: Option<String>,
+    // where uploads are pinned unless the request says otherwise
+    pub pin_strategy: PinStrategy,
+    // objects larger than this are stored but left unpinned
+    pub max_pinned_bytes: u64,
+    // CID version `add` produces (0 or 1)
+    pub cid_version: u8,
+    // store file data as raw blocks instead of UnixFS leaves (CIDv1 only)
+    pub raw_leaves: bool,
+    pub auth: AuthKind,
 }
 
-impl IpfsConfig {
-    //  IPFS_API_URL
+// How requests to the IPFS API authenticate
+#[derive(Clone, Default, PartialEq, Eq)]
+pub enum AuthKind {
+    #[default]
+    None,
+    // Infura-style project id/secret
+    Basic {
+        id: String,
+        secret: String,
+    },
+    Bearer {
+        token: String,
+    },
+}
+
+impl AuthKind {
+    //  IPFS_PROJECT_ID + IPFS_PROJECT_SECRET, or IPFS_BEARER_TOKEN
     pub fn from_env() -> Result<Self> {
-        let api_url =
-            env::var("IPFS_API_URL").context("IPFS_API_URL environment variable is required")?;
+        Self::from_lookup(|name| env::var(name).ok())
+    }
 
-        let project_id = env::var("IPFS_PROJECT_ID").ok();
-        let project_secret = env::var("IPFS_PROJECT_SECRET").ok();
+    // `from_env` with the variables read through `lookup`
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let non_empty = |name| lookup(name).filter(|value: &String| !value.is_empty());
+        let project_id = non_empty("IPFS_PROJECT_ID");
+        let project_secret = non_empty("IPFS_PROJECT_SECRET");
+        let bearer_token = non_empty("IPFS_BEARER_TOKEN");
 
-        if project_id.is_some() != project_secret.is_some() {
-            tracing::warn!(
-                "Both IPFS_PROJECT_ID and IPFS_PROJECT_SECRET should be set for authentication"
+        if bearer_token.is_some() && (project_id.is_some() || project_secret.is_some()) {
+            anyhow::bail!(
+                "Set either IPFS_BEARER_TOKEN or IPFS_PROJECT_ID/IPFS_PROJECT_SECRET, not both"
             );
         }
 
-        Ok(Self {
+        Ok(match (project_id, project_secret, bearer_token) {
+            (_, _, Some(token)) => AuthKind::Bearer { token },
+            (Some(id), Some(secret), None) => AuthKind::Basic { id, secret },
+            (None, None, None) => AuthKind::None,
+            _ => {
+                tracing::warn!(
+                    "Both IPFS_PROJECT_ID and IPFS_PROJECT_SECRET should be set for authentication"
+                );
+                AuthKind::None
+            }
+        })
+    }
+
+    pub fn apply(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self {
+            AuthKind::None => request,
+            AuthKind::Basic { id, secret } => request.basic_auth(id, Some(secret)),
+            AuthKind::Bearer { token } => request.bearer_auth(token),
+        }
+    }
+}
+
+// keeps credentials out of logged configs
+impl std::fmt::Debug for AuthKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthKind::None => f.write_str("None"),
+            AuthKind::Basic { id, .. } => write!(f, "Basic {{ id: {:?}, .. }}", id),
+            AuthKind::Bearer { .. } => f.write_str("Bearer { .. }"),
+        }
+    }
+}
+
+const DEFAULT_UPLOAD_CONCURRENCY: usize = 8;
+const DEFAULT_DNSLINK_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_PINNED_BYTES: u64 = 1024 * 1024 * 1024;
+
+impl IpfsConfig {
+    //  IPFS_MULTIADDR or IPFS_API_URL, IPFS_API_PATH_PREFIX, IPFS_PIN_ON_UPLOAD,
+    //  IPFS_PIPELINE or IPFS_COMPRESSION,
+    //  UPLOAD_CONCURRENCY, IPFS_CID_VERSION, IPFS_RAW_LEAVES, IPFS_DNSLINK_TTL_SECS,
+    //  MAX_PINNED_BYTES, DEGRADE_QUEUE_DEPTH, IPFS_REMOTE_PIN_SERVICE,
+    //  IPFS_PIN_STRATEGY
+    pub fn from_env() -> Result<Self> {
+        // a multiaddr, as in Kubo's `Addresses.API`, takes precedence over the URL
+        let api_url = match env::var("IPFS_MULTIADDR") {
+            Ok(addr) => multiaddr_to_url(&addr).context("Invalid IPFS_MULTIADDR")?,
+            Err(_) => env::var("IPFS_API_URL")
+                .context("IPFS_API_URL or IPFS_MULTIADDR environment variable is required")?,
+        };
+
+        let api_path_prefix = env::var("IPFS_API_PATH_PREFIX")
+            .map(|prefix| normalize_path_prefix(&prefix))
+            .unwrap_or_default();
+
+        let pin_on_upload = env::var("IPFS_PIN_ON_UPLOAD")
+            .map(|v| !matches!(v.to_lowercase().as_str(), "false" | "0" | "no"))
+            .unwrap_or(true);
+
+        // IPFS_COMPRESSION=gzip is shorthand for IPFS_PIPELINE=gzip
+        let pipeline = match (env::var("IPFS_PIPELINE"), env::var("IPFS_COMPRESSION")) {
+            (Ok(_), Ok(_)) => {
+                anyhow::bail!("Set IPFS_PIPELINE or IPFS_COMPRESSION, not both")
+            }
+            (Ok(spec), Err(_)) => Pipeline::parse(&spec)?,
+            (Err(_), Ok(codec)) => match codec.parse()? {
+                Compression::None => Pipeline::default(),
+                compression => Pipeline::new(vec![Box::new(compression)]),
+            },
+            (Err(_), Err(_)) => Pipeline::default(),
+        };
+
+        let upload_concurrency = match env::var("UPLOAD_CONCURRENCY") {
+            Ok(limit) => limit
+                .parse::<usize>()
+                .ok()
+                .filter(|limit| *limit > 0)
+                .context("UPLOAD_CONCURRENCY must be a positive integer")?,
+            Err(_) => DEFAULT_UPLOAD_CONCURRENCY,
+        };
+
+        let degrade_queue_depth = env::var("DEGRADE_QUEUE_DEPTH")
+            .ok()
+            .map(|depth| {
+                depth
+                    .parse::<usize>()
+                    .context("DEGRADE_QUEUE_DEPTH must be a non-negative integer")
+            })
+            .transpose()?;
+
+        let dnslink_ttl = match env::var("IPFS_DNSLINK_TTL_SECS") {
+            Ok(secs) => Duration::from_secs(
+                secs.parse()
+                    .context("IPFS_DNSLINK_TTL_SECS must be a number of seconds")?,
+            ),
+            Err(_) => DEFAULT_DNSLINK_TTL,
+        };
+
+        let remote_pin_service = env::var("IPFS_REMOTE_PIN_SERVICE")
+            .ok()
+            .map(|service| service.trim().to_string())
+            .filter(|service| !service.is_empty());
+
+        let pin_strategy = match env::var("IPFS_PIN_STRATEGY") {
+            Ok(strategy) => strategy.parse()?,
+            Err(_) => PinStrategy::Local,
+        };
+
+        let max_pinned_bytes = match env::var("MAX_PINNED_BYTES") {
+            Ok(limit) => limit
+                .parse::<u64>()
+                .context("MAX_PINNED_BYTES must be a number of bytes")?,
+            Err(_) => DEFAULT_MAX_PINNED_BYTES,
+        };
+
+        let cid_version = match env::var("IPFS_CID_VERSION") {
+            Ok(version) => match version.trim() {
+                "0" => 0,
+                "1" => 1,
+                other => anyhow::bail!("IPFS_CID_VERSION must be 0 or 1, got '{}'", other),
+            },
+            Err(_) => 1,
+        };
+
+        // Kubo's own default: raw leaves go with CIDv1
+        let raw_leaves = env::var("IPFS_RAW_LEAVES")
+            .map(|v| matches!(v.to_lowercase().as_str(), "true" | "1" | "yes"))
+            .unwrap_or(cid_version == 1);
+
+        let auth = AuthKind::from_env()?;
+
+        let config = Self {
             api_url,
-            project_id,
-            project_secret,
+            api_path_prefix,
+            pin_on_upload,
+            pipeline,
+            upload_concurrency,
+            degrade_queue_depth,
+            dnslink_ttl,
+            remote_pin_service,
+            pin_strategy,
+            max_pinned_bytes,
+            cid_version,
+            raw_leaves,
+            auth,
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.endpoint("add")?;
+
+        if self.cid_version > 1 {
+            anyhow::bail!("CID version must be 0 or 1, got {}", self.cid_version);
+        }
+        if self.raw_leaves && self.cid_version == 0 {
+            anyhow::bail!("raw leaves need CIDv1, set IPFS_CID_VERSION=1 or IPFS_RAW_LEAVES=false");
+        }
+        if self.pin_strategy.includes_remote() && self.remote_pin_service.is_none() {
+            anyhow::bail!(
+                "IPFS_PIN_STRATEGY={} needs IPFS_REMOTE_PIN_SERVICE",
+                self.pin_strategy
+            );
+        }
+
+        Ok(())
+    }
+
+    // query params every `add` call carries so CIDs match the configured format
+    fn add_params(&self) -> [(&'static str, String); 2] {
+        [
+            ("cid-version", self.cid_version.to_string()),
+            ("raw-leaves", self.raw_leaves.to_string()),
+        ]
+    }
+
+    // `{api_url}{prefix}/api/v0/{path}`, checked to be a valid URL
+    pub fn endpoint(&self, path: &str) -> Result<reqwest::Url> {
+        let url = format!(
+            "{}{}/api/v0/{}",
+            self.api_url.trim_end_matches('/'),
+            self.api_path_prefix,
+            path
+        );
+        reqwest::Url::parse(&url).with_context(|| format!("Invalid IPFS API URL: {}", url))
+    }
+}
+
+// Cheap shape check for CIDs taken from user input: CIDv0 (`Qm...`) or a
+// base32/base58btc multibase CIDv1. It doesn't decode the multihash.
+pub fn validate_cid(cid: &str) -> Result<()> {
+    const BASE58: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+    let valid = if cid.len() == 46 && cid.starts_with("Qm") {
+        cid.chars().all(|c| BASE58.contains(c))
+    } else if let Some(rest) = cid.strip_prefix('b') {
+        rest.len() >= 8 && rest.chars().all(|c| matches!(c, 'a'..='z' | '2'..='7'))
+    } else if let Some(rest) = cid.strip_prefix('z') {
+        rest.len() >= 8 && rest.chars().all(|c| BASE58.contains(c))
+    } else {
+        false
+    };
+
+    if !valid {
+        anyhow::bail!("Invalid CID: '{}'", cid);
+    }
+    Ok(())
+}
+
+// Checks an IPLD path below a CID (`a/b/0`) and returns it without slashes at the ends
+pub fn validate_ipld_path(path: &str) -> Result<String> {
+    let trimmed = path.trim_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+
+    for segment in trimmed.split('/') {
+        let valid = !segment.is_empty()
+            && segment != "."
+            && segment != ".."
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !valid {
+            anyhow::bail!("Invalid path segment '{}'", segment);
+        }
+    }
+
+    Ok(trimmed.to_string())
+}
+
+// DNS name as used in a DNSLink lookup
+pub fn validate_domain(domain: &str) -> Result<()> {
+    let domain = domain.trim_end_matches('.');
+    let valid = !domain.is_empty()
+        && domain.len() <= 253
+        && domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        });
+
+    if !valid {
+        anyhow::bail!("Invalid domain: '{}'", domain);
+    }
+    Ok(())
+}
+
+// Kubo reports missing blocks and unresolvable paths as 500s with these messages
+fn is_not_found(error_body: &str) -> bool {
+    [
+        "not found",
+        "no link named",
+        "could not resolve",
+        "no such link",
+    ]
+    .iter()
+    .any(|needle| error_body.contains(needle))
+}
+
+// multipart file names are URL-decoded by the node
+fn encode_path(path: &str) -> String {
+    url::form_urlencoded::byte_serialize(path.as_bytes()).collect()
+}
+
+// "/ip4/127.0.0.1/tcp/5001" -> "http://127.0.0.1:5001".
+//
+// Accepts an /ip4, /ip6, /dns, /dns4 or /dns6 host, a /tcp port and an
+// optional trailing /http or /https; any other protocol is rejected.
+pub fn multiaddr_to_url(addr: &str) -> Result<String> {
+    let mut components = addr.trim().trim_start_matches('/').split('/');
+    let mut next = |what: &str| {
+        components
+            .next()
+            .filter(|value| !value.is_empty())
+            .with_context(|| format!("Multiaddr '{}' is missing {}", addr, what))
+    };
+
+    let host = match next("a host component")? {
+        "ip4" => next("an IPv4 address")?
+            .parse::<std::net::Ipv4Addr>()
+            .with_context(|| format!("Multiaddr '{}' has an invalid IPv4 address", addr))?
+            .to_string(),
+        "ip6" => format!(
+            "[{}]",
+            next("an IPv6 address")?
+                .parse::<std::net::Ipv6Addr>()
+                .with_context(|| format!("Multiaddr '{}' has an invalid IPv6 address", addr))?
+        ),
+        "dns" | "dns4" | "dns6" => next("a hostname")?.to_string(),
+        other => anyhow::bail!("Unsupported multiaddr host protocol '/{}'", other),
+    };
+
+    let port = match next("a /tcp component")? {
+        "tcp" => next("a TCP port")?
+            .parse::<u16>()
+            .with_context(|| format!("Multiaddr '{}' has an invalid TCP port", addr))?,
+        other => anyhow::bail!(
+            "Unsupported multiaddr transport '/{}', only /tcp can reach the HTTP API",
+            other
+        ),
+    };
+
+    let scheme = match components.next() {
+        None | Some("") | Some("http") => "http",
+        Some("https") => "https",
+        Some(other) => anyhow::bail!("Unsupported multiaddr protocol '/{}'", other),
+    };
+    if let Some(extra) = components.find(|c| !c.is_empty()) {
+        anyhow::bail!("Unsupported trailing multiaddr component '/{}'", extra);
+    }
+
+    Ok(format!("{}://{}:{}", scheme, host, port))
+}
+
+// "ipfs-api/" -> "/ipfs-api", "" and "/" -> ""
+fn normalize_path_prefix(prefix: &str) -> String {
+    let prefix = prefix.trim().trim_matches('/');
+    if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", prefix)
+    }
+}
+
+// How a CID is pinned on the node.
+//
+// `Recursive` pins the root and every block reachable from it, which is what
+// an imported DAG or directory needs to survive GC but touches every block.
+// `Direct` pins only the root block: cheap, and enough for the small JSON
+// metadata objects this service writes since they fit in a single block.
+// Direct-pinning a multi-block object leaves its children collectable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PinType {
+    Direct,
+    #[default]
+    Recursive,
+}
+
+// Where an upload is pinned: on our node, at the remote pinning service, or both.
+//
+// Local pins cost our own disk; remote pins survive losing the node but are
+// billed by the service.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PinStrategy {
+    #[default]
+    Local,
+    Remote,
+    Both,
+}
+
+impl PinStrategy {
+    pub fn includes_local(&self) -> bool {
+        matches!(self, PinStrategy::Local | PinStrategy::Both)
+    }
+
+    pub fn includes_remote(&self) -> bool {
+        matches!(self, PinStrategy::Remote | PinStrategy::Both)
+    }
+}
+
+impl FromStr for PinStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "local" => Ok(PinStrategy::Local),
+            "remote" => Ok(PinStrategy::Remote),
+            "both" => Ok(PinStrategy::Both),
+            other => anyhow::bail!(
+                "Unknown pin strategy '{}', expected local, remote or both",
+                other
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for PinStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PinStrategy::Local => "local",
+            PinStrategy::Remote => "remote",
+            PinStrategy::Both => "both",
         })
     }
 }
 
+// objects up to one default chunk are stored as a single block
+const SINGLE_BLOCK_MAX_BYTES: usize = 256 * 1024;
+
+impl PinType {
+    // direct for content that fits in one block, recursive otherwise
+    pub fn for_size(size_bytes: usize) -> Self {
+        if size_bytes <= SINGLE_BLOCK_MAX_BYTES {
+            PinType::Direct
+        } else {
+            PinType::Recursive
+        }
+    }
+
+    pub fn is_recursive(&self) -> bool {
+        matches!(self, PinType::Recursive)
+    }
+}
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 struct IpfsAddResponse {
     // CID
     hash: String,
-    #[allow(dead_code)]
     name: String,
     #[allow(dead_code)]
     size: String,
 }
 
+// `add` answers with one JSON object per line, one per added file/directory.
+//
+// With `progress=true` the node interleaves `{"Name":..,"Bytes":..}` frames;
+// those carry no `Hash` and are skipped.
+fn parse_add_ndjson(body: &str) -> Result<Vec<IpfsAddResponse>> {
+    let mut entries = Vec::new();
+
+    for line in body.lines().filter(|line| !line.trim().is_empty()) {
+        let frame: serde_json::Value =
+            serde_json::from_str(line).context("Failed to parse IPFS API response")?;
+        if frame.get("Hash").is_none() {
+            continue;
+        }
+        entries.push(serde_json::from_value(frame).context("Failed to parse IPFS API response")?);
+    }
+
+    Ok(entries)
+}
+
+// Checks a relative path for a directory upload and returns it normalized.
+//
+// Rejects absolute paths, `.`/`..` segments, backslashes and control
+// characters so no entry can escape the wrapping directory.
+pub fn normalize_dir_path(path: &str) -> Result<String> {
+    let trimmed = path.trim().trim_end_matches('/');
+
+    if trimmed.is_empty() {
+        anyhow::bail!("Empty file path");
+    }
+    if trimmed.starts_with('/') || trimmed.contains('\\') {
+        anyhow::bail!("File path '{}' must be relative and use '/'", path);
+    }
+    if trimmed.chars().any(char::is_control) {
+        anyhow::bail!("File path '{}' contains control characters", path);
+    }
+
+    let segments: Vec<&str> = trimmed.split('/').collect();
+    if segments
+        .iter()
+        .any(|segment| segment.is_empty() || *segment == "." || *segment == "..")
+    {
+        anyhow::bail!(
+            "File path '{}' contains an empty, '.' or '..' segment",
+            path
+        );
+    }
+
+    Ok(segments.join("/"))
+}
+
+// root of a directory upload plus the CID of every file in it
+#[derive(Debug, Clone, Serialize)]
+pub struct IpfsDirectoryResult {
+    pub root_cid: String,
+    pub files: Vec<IpfsDirectoryEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IpfsDirectoryEntry {
+    pub path: String,
+    pub cid: String,
+}
+
+// outcome of an upload; `deduplicated` when the node already had the CID pinned
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IpfsAddResult {
+    pub cid: String,
+    pub deduplicated: bool,
+    pub pinned: bool,
+    // why a requested pin was skipped
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct IpfsObjectStat {
+    // file content size, what `cat` returns
+    pub size: u64,
+    // whole DAG including UnixFS overhead, what a pin keeps
+    pub cumulative_size: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct IpfsResolveResponse {
+    path: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct IpfsVersionResponse {
+    version: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct IpfsPinResponse {
+    pins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RemotePin {
+    pub cid: String,
+    #[serde(default)]
+    pub name: String,
+    // queued, pinning, pinned or failed
+    pub status: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RefsLine {
+    #[serde(default)]
+    r#ref: String,
+    #[serde(default)]
+    err: String,
+}
+
+// one line of a `dag/import` response: a root and whether pinning it
+// worked, or the closing block stats
+#[derive(Debug, Deserialize)]
+struct DagImportLine {
+    #[serde(rename = "Root")]
+    root: Option<DagImportRoot>,
+    #[serde(rename = "Stats")]
+    stats: Option<DagImportStats>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DagImportStats {
+    block_count: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DagImportRoot {
+    cid: DagLink,
+    #[serde(default)]
+    pin_error_msg: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DagLink {
+    #[serde(rename = "/")]
+    cid: String,
+}
+
+// what the node reports back from a `dag/import`
+#[derive(Debug, Clone)]
+pub struct CarImport {
+    pub roots: Vec<ImportedRoot>,
+    // blocks the node read from the archive
+    pub blocks: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportedRoot {
+    pub cid: String,
+    pub pinned: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// the node has no remote pinning service by that name
+#[derive(Debug, thiserror::Error)]
+#[error("Remote pinning service '{0}' is not configured on the IPFS node")]
+pub struct UnknownPinService(pub String);
+
 #[derive(Debug, Clone)]
 pub struct IpfsClient {
     http_client: reqwest::Client,
     config: IpfsConfig,
+    // shared by clones so the limit holds across the whole process
+    upload_permits: Arc<Semaphore>,
+    uploads_waiting: Arc<AtomicUsize>,
+    last_error: Arc<Mutex<Option<(SystemTime, String)>>>,
+    dnslink_cache: Arc<DnslinkCache>,
+    in_flight_uploads: Arc<InFlightUploads>,
+}
+
+// short-lived domain -> CID resolutions, with hit/miss counters for status
+#[derive(Debug, Default)]
+struct DnslinkCache {
+    entries: Mutex<HashMap<String, (String, Instant)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl DnslinkCache {
+    fn get(&self, domain: &str, ttl: Duration) -> Option<String> {
+        let entries = self.entries.lock().ok()?;
+        let cid = entries
+            .get(domain)
+            .filter(|(_, resolved_at)| resolved_at.elapsed() < ttl)
+            .map(|(cid, _)| cid.clone());
+
+        let counter = if cid.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cid
+    }
+
+    fn insert(&self, domain: &str, cid: &str, ttl: Duration) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.retain(|_, (_, resolved_at)| resolved_at.elapsed() < ttl);
+            entries.insert(domain.to_string(), (cid.to_string(), Instant::now()));
+        }
+    }
+}
+
+// (sha2-256 of the bytes before the pipeline runs, pin)
+type UploadKey = ([u8; 32], bool);
+type UploadFlight = OnceCell<Result<IpfsAddResult, Arc<anyhow::Error>>>;
+
+// Uploads currently talking to the node, so identical concurrent uploads
+// make one request and share its result. An entry lives only while its
+// upload runs; later uploads of the same content go through dedup instead.
+#[derive(Debug, Default)]
+struct InFlightUploads {
+    uploads: Mutex<HashMap<UploadKey, Arc<UploadFlight>>>,
+}
+
+impl InFlightUploads {
+    fn join(&self, key: UploadKey) -> Arc<UploadFlight> {
+        match self.uploads.lock() {
+            Ok(mut uploads) => uploads.entry(key).or_default().clone(),
+            Err(_) => Arc::default(),
+        }
+    }
+
+    fn finish(&self, key: &UploadKey, flight: &Arc<UploadFlight>) {
+        let Ok(mut uploads) = self.uploads.lock() else {
+            return;
+        };
+        if uploads
+            .get(key)
+            .is_some_and(|current| Arc::ptr_eq(current, flight))
+        {
+            uploads.remove(key);
+        }
+    }
+}
+
+// an error from a coalesced upload, handed to every caller that joined it
+#[derive(Debug)]
+struct SharedUploadError(Arc<anyhow::Error>);
+
+impl std::fmt::Display for SharedUploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the top-level message only; the rest of the chain comes through `source`
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for SharedUploadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
 }
 
 impl IpfsClient {
     pub fn new(config: IpfsConfig) -> Self {
         let http_client = reqwest::Client::new();
+        let upload_permits = Arc::new(Semaphore::new(config.upload_concurrency));
         Self {
             http_client,
             config,
+            upload_permits,
+            uploads_waiting: Arc::new(AtomicUsize::new(0)),
+            last_error: Arc::new(Mutex::new(None)),
+            dnslink_cache: Arc::new(DnslinkCache::default()),
+            in_flight_uploads: Arc::new(InFlightUploads::default()),
         }
     }
 
@@ -66,7 +787,7 @@ impl IpfsClient {
     }
 
     // ?????????????????????????????
-    pub async fn upload_json<T: Serialize>(&self, value: &T) -> Result<String> {
+    pub async fn upload_json<T: Serialize>(&self, value: &T, pin: bool) -> Result<IpfsAddResult> {
         tracing::debug!("Serializing JSON data for IPFS upload");
 
         let json_bytes = serde_json::to_vec(value).context("Failed to serialize value to JSON")?;
@@ -76,174 +797,2019 @@ impl IpfsClient {
             "JSON serialized, uploading to IPFS"
         );
 
-        self.upload_bytes(json_bytes).await
+        self.upload_bytes(json_bytes, pin).await
     }
 
     // Uploads raw bytes to IPFS
     //
     // Uploads the provided bytes directly to IPFS.
-    // Returns the CID (Content Identifier) hash as a string.
+    // Returns the CID (Content Identifier) hash and whether the node already
+    // had it pinned, in which case the upload itself is skipped.
     //
     // # Arguments
     // * `bytes` - Raw bytes to upload
+    // * `pin` - Pin the content after adding it (see `PinType::for_size`)
     //
     // # Returns
-    // * `Result<String>` - The CID hash on success
+    // * `Result<IpfsAddResult>` - The CID hash, dedup and pin flags on success
     //
     // # Example
     // ```ignore
     // let data = b"Hello, IPFS!".to_vec();
-    // let cid = ipfs_client.upload_bytes(data).await?;
+    // let cid = ipfs_client.upload_bytes(data, true).await?.cid;
     // ```
     // ???????????????????????????????????????????????????
-    pub async fn upload_bytes(&self, bytes: Vec<u8>) -> Result<String> {
-        let url = format!("{}/api/v0/add", self.config.api_url);
+    pub async fn upload_bytes(&self, bytes: Vec<u8>, pin: bool) -> Result<IpfsAddResult> {
+        // callers uploading the same bytes at the same time share one upload;
+        // if the one running it is cancelled, the next waiter takes over
+        let key = (Sha256::digest(&bytes).into(), pin);
+        let flight = self.in_flight_uploads.join(key);
+        let result = flight
+            .get_or_init(|| async { self.upload_once(bytes, pin).await.map_err(Arc::new) })
+            .await
+            .clone();
+        self.in_flight_uploads.finish(&key, &flight);
+
+        result.map_err(|e| SharedUploadError(e).into())
+    }
+
+    async fn upload_once(&self, bytes: Vec<u8>, pin: bool) -> Result<IpfsAddResult> {
+        let bytes = self.config.pipeline.apply(bytes)?;
+
+        // a pipeline that encrypts never produces the same bytes twice
+        let existing = if self.config.pipeline.is_deterministic() {
+            self.find_existing(&bytes).await
+        } else {
+            Ok(None)
+        };
+        match existing {
+            Ok(Some(cid)) => {
+                tracing::info!(cid = %cid, "Content already pinned on IPFS, skipping upload");
+                // `is_pinned` saw a pin, but it only counts as this upload's
+                // when the caller asked for one
+                return Ok(IpfsAddResult {
+                    cid,
+                    deduplicated: true,
+                    pinned: pin,
+                    warning: None,
+                });
+            }
+            Ok(None) => {}
+            Err(e) => {
+                // dedup is only an optimisation, a failed check shouldn't block the upload
+                tracing::warn!(error = %e, "IPFS duplicate check failed, uploading anyway");
+            }
+        }
+
+        let pin_type = PinType::for_size(bytes.len());
+        let cid = self.add(bytes, false).await?;
 
         tracing::info!(
-            url = %url,
-            size_bytes = bytes.len(),
-            "Uploading data to IPFS"
+            cid = %cid,
+            "Successfully uploaded to IPFS"
         );
 
-        // Create multipart form with the file data
-        let part = Part::bytes(bytes)
-            .file_name("file")
-            .mime_str("application/octet-stream")
-            .context("Failed to set MIME type")?;
+        let mut warning = None;
+        if pin {
+            // the content is stored by now, so a failed size check only costs the pin
+            warning = match self.check_pin_size(&cid).await {
+                Ok(warning) => warning,
+                Err(e) => {
+                    tracing::warn!(
+                        cid = %cid,
+                        error = %e,
+                        "Size check failed after upload, not pinning"
+                    );
+                    Some("Object size could not be checked; stored but not pinned".to_string())
+                }
+            };
+            if warning.is_none() {
+                self.pin(&cid, pin_type).await?;
+            }
+        }
 
-        let form = Form::new().part("file", part);
+        Ok(IpfsAddResult {
+            cid,
+            deduplicated: false,
+            pinned: pin && warning.is_none(),
+            warning,
+        })
+    }
 
-        // Build the request
-        let mut request = self.http_client.post(&url).multipart(form);
+    // Asks the node to pin `cid` at a remote pinning service.
+    //
+    // Runs in the background on the node, so the returned status is usually
+    // `queued`. Fails with `UnknownPinService` when the node doesn't know the
+    // service.
+    pub async fn pin_remote(&self, cid: &str, service: &str) -> Result<RemotePin> {
+        let url = self.config.endpoint("pin/remote/add")?;
 
-        // Add Basic Auth if credentials are provided
-        if let (Some(ref project_id), Some(ref project_secret)) =
-            (&self.config.project_id, &self.config.project_secret)
-        {
-            tracing::debug!("Using Basic Auth for IPFS request");
-            request = request.basic_auth(project_id, Some(project_secret));
-        }
+        tracing::info!(cid = %cid, service = %service, "Pinning CID at remote pinning service");
 
-        // Send the request
-        let response = request
-            .send()
-            .await
-            .context("Failed to send request to IPFS API")?;
+        let request = self.authorize(self.http_client.post(url).query(&[
+            ("arg", cid),
+            ("service", service),
+            ("background", "true"),
+        ]));
+        let response = self.transmit(request).await?;
 
-        // Check for HTTP errors
         let status = response.status();
         if !status.is_success() {
-            let error_body = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            tracing::error!(
-                status = %status,
-                body = %error_body,
-                "IPFS API returned error"
-            );
-            anyhow::bail!("IPFS API error ({}): {}", status, error_body);
+            let error_body = response.text().await.unwrap_or_default();
+            if error_body.contains("service not known") {
+                return Err(UnknownPinService(service.to_string()).into());
+            }
+            return Err(self.api_error(status, &error_body));
         }
 
-        // Parse the response
-        let ipfs_response: IpfsAddResponse = response
+        response
             .json()
             .await
-            .context("Failed to parse IPFS API response")?;
+            .context("Failed to parse IPFS remote pin response")
+    }
 
-        tracing::info!(
-            cid = %ipfs_response.hash,
-            "Successfully uploaded to IPFS"
+    // Imports a CARv1 archive's blocks and pins its roots recursively.
+    //
+    // The node stores the blocks as given, so callers should verify them
+    // first (`CarBlock::verify`).
+    pub async fn import_car(&self, car: Vec<u8>) -> Result<CarImport> {
+        let url = self.config.endpoint("dag/import")?;
+        let _permit = self.acquire_upload_slot().await?;
+
+        tracing::info!(url = %url, bytes = car.len(), "Importing CAR into IPFS");
+
+        let part = Part::bytes(car)
+            .file_name("import.car")
+            .mime_str("application/vnd.ipld.car")
+            .context("Failed to set MIME type")?;
+        let request = self.authorize(
+            self.http_client
+                .post(url)
+                .query(&[("pin-roots", "true"), ("stats", "true")])
+                .multipart(Form::new().part("file", part)),
         );
+        let response = self.send(request).await?;
 
-        Ok(ipfs_response.hash)
+        let body = response
+            .text()
+            .await
+            .context("Failed to read IPFS API response")?;
+        let mut roots = Vec::new();
+        let mut blocks = None;
+        for line in body.lines().filter(|line| !line.trim().is_empty()) {
+            let line: DagImportLine =
+                serde_json::from_str(line).context("Failed to parse IPFS dag/import response")?;
+            if let Some(root) = line.root {
+                let error = Some(root.pin_error_msg).filter(|msg| !msg.is_empty());
+                roots.push(ImportedRoot {
+                    cid: root.cid.cid,
+                    pinned: error.is_none(),
+                    error,
+                });
+            }
+            if let Some(stats) = line.stats {
+                blocks = Some(stats.block_count);
+            }
+        }
+        let blocks = blocks.context("IPFS dag/import response has no block stats")?;
+        Ok(CarImport { roots, blocks })
     }
 
-    pub fn api_url(&self) -> &str {
-        &self.config.api_url
+    pub fn remote_pin_service(&self) -> Option<&str> {
+        self.config.remote_pin_service.as_deref()
     }
 
-    pub fn has_auth(&self) -> bool {
-        self.config.project_id.is_some() && self.config.project_secret.is_some()
+    pub fn pin_strategy(&self) -> PinStrategy {
+        self.config.pin_strategy
     }
-}
-
-use std::sync::Arc;
 
-#[derive(Clone)]
-pub struct AppState {
-    pub ipfs_client: Arc<IpfsClient>,
-    // database pool config
-}
+    // a warning when the object is over `max_pinned_bytes` and must not be pinned
+    async fn check_pin_size(&self, cid: &str) -> Result<Option<String>> {
+        let size = self
+            .stat(cid)
+            .await?
+            .context("Uploaded object not found on IPFS")?
+            .cumulative_size;
 
-impl AppState {
-    pub fn new() -> Result<Self> {
-        let ipfs_client = IpfsClient::from_env()?;
-        Ok(Self {
-            ipfs_client: Arc::new(ipfs_client),
-        })
+        Ok(self
+            .check_pin_limit(cid, size)
+            .map(|reason| format!("{}; stored but not pinned", reason)))
     }
 
-    pub fn with_ipfs_client(ipfs_client: IpfsClient) -> Self {
-        Self {
-            ipfs_client: Arc::new(ipfs_client),
+    // why an object of `size` bytes can't be pinned, `None` when it is within `max_pinned_bytes`
+    pub fn check_pin_limit(&self, cid: &str, size: u64) -> Option<String> {
+        if size <= self.config.max_pinned_bytes {
+            return None;
         }
-    }
+
+        tracing::warn!(
+            cid = %cid,
+            size_bytes = size,
+            limit_bytes = self.config.max_pinned_bytes,
+            "Object exceeds MAX_PINNED_BYTES, not pinning"
+        );
+        Some(format!(
+            "Object is {} bytes, over the {} byte pin limit",
+            size, self.config.max_pinned_bytes
+        ))
+    }
+
+    // sizes of the object at `cid`; `None` when the node can't find it
+    pub async fn stat(&self, cid: &str) -> Result<Option<IpfsObjectStat>> {
+        let url = self.config.endpoint("files/stat")?;
+        let arg = format!("/ipfs/{}", cid);
+
+        let request = self.authorize(self.http_client.post(url).query(&[("arg", arg.as_str())]));
+        let Some(response) = self.send_optional(request).await? else {
+            return Ok(None);
+        };
+
+        let stat = response
+            .json()
+            .await
+            .context("Failed to parse IPFS stat response")?;
+
+        Ok(Some(stat))
+    }
+
+    // CID `upload_bytes` would return for `bytes`, computed locally.
+    //
+    // Applies the configured pipeline, CID version and raw-leaves setting;
+    // only content that fits in one 256 KiB block is supported, see
+    // `cid::compute`.
+    pub fn compute_cid(&self, bytes: &[u8]) -> Result<String> {
+        if !self.config.pipeline.is_deterministic() {
+            anyhow::bail!(
+                "IPFS_PIPELINE {:?} changes the bytes on every upload, so CIDs can't be computed ahead of time",
+                self.config.pipeline.names().join(",")
+            );
+        }
+        let bytes = self.config.pipeline.apply(bytes.to_vec())?;
+        crate::cid::compute(&bytes, self.config.cid_version, self.config.raw_leaves)
+    }
+
+    // CID `upload_json` would return for `value`
+    pub fn compute_cid_json<T: Serialize>(&self, value: &T) -> Result<String> {
+        let json_bytes = serde_json::to_vec(value).context("Failed to serialize value to JSON")?;
+        self.compute_cid(&json_bytes)
+    }
+
+    // CID the bytes would get, if the node already has it pinned.
+    //
+    // Single-block content is hashed locally; only larger objects are sent to
+    // the node with `only-hash`.
+    async fn find_existing(&self, bytes: &[u8]) -> Result<Option<String>> {
+        let cid = if bytes.len() <= crate::cid::MAX_SINGLE_BLOCK_BYTES {
+            crate::cid::compute(bytes, self.config.cid_version, self.config.raw_leaves)?
+        } else {
+            self.add(bytes.to_vec(), true).await?
+        };
+
+        if self.is_pinned(&cid).await? {
+            Ok(Some(cid))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // `/api/v0/add` without pinning; with `only_hash` the node computes the CID without storing anything
+    async fn add(&self, bytes: Vec<u8>, only_hash: bool) -> Result<String> {
+        let url = self.config.endpoint("add")?;
+        let _permit = self.acquire_upload_slot().await?;
+
+        tracing::info!(
+            url = %url,
+            size_bytes = bytes.len(),
+            only_hash,
+            "Uploading data to IPFS"
+        );
+
+        // Create multipart form with the file data
+        let part = Part::bytes(bytes)
+            .file_name("file")
+            .mime_str("application/octet-stream")
+            .context("Failed to set MIME type")?;
+
+        let form = Form::new().part("file", part);
+
+        // Build and send the request
+        let request = self.authorize(
+            self.http_client
+                .post(url)
+                .query(&[
+                    ("only-hash", only_hash.to_string().as_str()),
+                    ("pin", "false"),
+                ])
+                .query(&self.config.add_params())
+                .multipart(form),
+        );
+        let response = self.send(request).await?;
+
+        // Parse the response; the final object is the added file
+        let body = response
+            .text()
+            .await
+            .context("Failed to read IPFS API response")?;
+        let ipfs_response = parse_add_ndjson(&body)?
+            .pop()
+            .context("IPFS add response contained no result")?;
+
+        Ok(ipfs_response.hash)
+    }
+
+    // Adds `(relative path, bytes)` files as one UnixFS directory.
+    //
+    // Uses `wrap-with-directory` so the node returns a root CID that links every
+    // file under its path; the root is pinned recursively. Paths must already be
+    // normalized with `normalize_dir_path`.
+    pub async fn add_directory(
+        &self,
+        files: Vec<(String, Vec<u8>)>,
+    ) -> Result<IpfsDirectoryResult> {
+        let url = self.config.endpoint("add")?;
+        let _permit = self.acquire_upload_slot().await?;
+
+        tracing::info!(url = %url, file_count = files.len(), "Uploading directory to IPFS");
+
+        // parent directories have to appear before the files inside them
+        let mut directories: Vec<String> = Vec::new();
+        for (path, _) in &files {
+            for (index, _) in path.match_indices('/') {
+                let parent = &path[..index];
+                if !directories.iter().any(|directory| directory == parent) {
+                    directories.push(parent.to_string());
+                }
+            }
+        }
+
+        let mut form = Form::new();
+        for directory in &directories {
+            let part = Part::bytes(Vec::new())
+                .file_name(encode_path(directory))
+                .mime_str("application/x-directory")
+                .context("Failed to set MIME type")?;
+            form = form.part("file", part);
+        }
+
+        let file_paths: Vec<String> = files.iter().map(|(path, _)| path.clone()).collect();
+        for (path, bytes) in files {
+            let part = Part::bytes(bytes)
+                .file_name(encode_path(&path))
+                .mime_str("application/octet-stream")
+                .context("Failed to set MIME type")?;
+            form = form.part("file", part);
+        }
+
+        let request = self.authorize(
+            self.http_client
+                .post(url)
+                .query(&[("wrap-with-directory", "true"), ("pin", "false")])
+                .query(&self.config.add_params())
+                .multipart(form),
+        );
+        let response = self.send(request).await?;
+
+        let body = response
+            .text()
+            .await
+            .context("Failed to read IPFS API response")?;
+        let entries = parse_add_ndjson(&body)?;
+
+        let root_cid = entries
+            .iter()
+            .find(|entry| entry.name.is_empty())
+            .map(|entry| entry.hash.clone())
+            .context("IPFS API response did not include the wrapping directory")?;
+
+        let files = file_paths
+            .into_iter()
+            .filter_map(|path| {
+                let cid = entries
+                    .iter()
+                    .find(|entry| entry.name == path)?
+                    .hash
+                    .clone();
+                Some(IpfsDirectoryEntry { path, cid })
+            })
+            .collect();
+
+        self.pin(&root_cid, PinType::Recursive).await?;
+
+        tracing::info!(root_cid = %root_cid, "Successfully uploaded directory to IPFS");
+
+        Ok(IpfsDirectoryResult { root_cid, files })
+    }
+
+    // `length` bytes of the stored object from `offset` (to the end when `None`),
+    // exactly as stored, without decompression
+    pub async fn cat_range(&self, cid: &str, offset: u64, length: Option<u64>) -> Result<Vec<u8>> {
+        let url = self.config.endpoint("cat")?;
+
+        let mut query = vec![("arg", cid.to_string()), ("offset", offset.to_string())];
+        if let Some(length) = length {
+            query.push(("length", length.to_string()));
+        }
+
+        tracing::debug!(cid = %cid, offset, length, "Fetching object range from IPFS");
+
+        let request = self.authorize(self.http_client.post(url).query(&query));
+        let response = self.send(request).await?;
+
+        let bytes = response
+            .bytes()
+            .await
+            .context("Failed to read IPFS object body")?;
+
+        Ok(bytes.to_vec())
+    }
+
+    // Fetches an object's bytes, undoing the pipeline applied on upload;
+    // `None` when the node can't find it
+    pub async fn get_bytes(&self, cid: &str) -> Result<Option<Vec<u8>>> {
+        let url = self.config.endpoint("cat")?;
+
+        tracing::debug!(cid = %cid, "Fetching object from IPFS");
+
+        let request = self.authorize(self.http_client.post(url).query(&[("arg", cid)]));
+        let Some(response) = self.send_optional(request).await? else {
+            return Ok(None);
+        };
+
+        let bytes = response
+            .bytes()
+            .await
+            .context("Failed to read IPFS object body")?;
+
+        self.config.pipeline.invert(bytes.to_vec()).map(Some)
+    }
+
+    // Resolves `{cid}/{path}` through the DAG API; `None` when the node has no such path
+    pub async fn dag_get(&self, cid: &str, path: &str) -> Result<Option<serde_json::Value>> {
+        let url = self.config.endpoint("dag/get")?;
+        let arg = if path.is_empty() {
+            cid.to_string()
+        } else {
+            format!("{}/{}", cid, path)
+        };
+
+        tracing::debug!(arg = %arg, "Resolving DAG path on IPFS");
+
+        let request = self.authorize(self.http_client.post(url).query(&[("arg", arg.as_str())]));
+        let Some(response) = self.send_optional(request).await? else {
+            return Ok(None);
+        };
+
+        let node = response
+            .json()
+            .await
+            .context("Failed to parse IPFS DAG node")?;
+
+        Ok(Some(node))
+    }
+
+    // CIDs the object links to directly, each once; `None` when the node doesn't have it
+    pub async fn refs(&self, cid: &str) -> Result<Option<Vec<String>>> {
+        let url = self.config.endpoint("refs")?;
+
+        tracing::debug!(cid = %cid, "Listing object links on IPFS");
+
+        let request = self.authorize(
+            self.http_client
+                .post(url)
+                .query(&[("arg", cid), ("unique", "true")]),
+        );
+        let Some(response) = self.send_optional(request).await? else {
+            return Ok(None);
+        };
+
+        let body = response
+            .text()
+            .await
+            .context("Failed to read IPFS API response")?;
+
+        // refs streams its answer, so a failure part-way arrives as a line with `Err`
+        let mut refs = Vec::new();
+        for line in body.lines().filter(|line| !line.trim().is_empty()) {
+            let entry: RefsLine =
+                serde_json::from_str(line).context("Failed to parse IPFS refs response")?;
+            if !entry.err.is_empty() {
+                if is_not_found(&entry.err) {
+                    return Ok(None);
+                }
+                anyhow::bail!("IPFS refs failed: {}", entry.err);
+            }
+            refs.push(entry.r#ref);
+        }
+        Ok(Some(refs))
+    }
+
+    // Whether the node holds any pin (direct, recursive or indirect) for the CID
+    pub async fn is_pinned(&self, cid: &str) -> Result<bool> {
+        let url = self.config.endpoint("pin/ls")?;
+
+        let response = self
+            .transmit(self.authorize(self.http_client.post(url).query(&[("arg", cid)])))
+            .await?;
+
+        if response.status().is_success() {
+            return Ok(true);
+        }
+
+        let status = response.status();
+        let error_body = response.text().await.unwrap_or_default();
+        if error_body.contains("not pinned") {
+            Ok(false)
+        } else {
+            Err(self.api_error(status, &error_body))
+        }
+    }
+
+    // Pins a CID on the node, see `PinType` for the tradeoffs
+    pub async fn pin(&self, cid: &str, pin_type: PinType) -> Result<()> {
+        let url = self.config.endpoint("pin/add")?;
+
+        tracing::info!(cid = %cid, pin_type = ?pin_type, "Pinning CID on IPFS");
+
+        let recursive = pin_type.is_recursive().to_string();
+        let request = self.authorize(
+            self.http_client
+                .post(url)
+                .query(&[("arg", cid), ("recursive", recursive.as_str())]),
+        );
+        let response = self.send(request).await?;
+
+        let pin_response: IpfsPinResponse = response
+            .json()
+            .await
+            .context("Failed to parse IPFS pin response")?;
+
+        if !pin_response.pins.iter().any(|pinned| pinned == cid) {
+            tracing::warn!(
+                cid = %cid,
+                pins = ?pin_response.pins,
+                "IPFS pin response did not echo the CID"
+            );
+        }
+
+        Ok(())
+    }
+
+    // Adds the configured credentials (Basic or Bearer), if any
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        self.config.auth.apply(request)
+    }
+
+    // Sends the request and turns non-2xx statuses into errors
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let response = self.transmit(request).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_body = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(self.api_error(status, &error_body));
+        }
+
+        Ok(response)
+    }
+
+    async fn transmit(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        request.send().await.map_err(|e| {
+            self.record_error(format!("Failed to send request to IPFS API: {}", e));
+            anyhow::Error::new(e).context("Failed to send request to IPFS API")
+        })
+    }
+
+    fn api_error(&self, status: reqwest::StatusCode, error_body: &str) -> anyhow::Error {
+        tracing::error!(
+            status = %status,
+            body = %error_body,
+            "IPFS API returned error"
+        );
+        let message = format!("IPFS API error ({}): {}", status, error_body);
+        self.record_error(message.clone());
+        anyhow::anyhow!(message)
+    }
+
+    fn record_error(&self, message: String) {
+        if let Ok(mut last_error) = self.last_error.lock() {
+            *last_error = Some((SystemTime::now(), message));
+        }
+    }
+
+    // when and why the last call to the node failed
+    pub fn last_error(&self) -> Option<(SystemTime, String)> {
+        self.last_error.lock().ok().and_then(|last| last.clone())
+    }
+
+    // Waits for one of the `upload_concurrency` slots; the permit frees it on drop
+    async fn acquire_upload_slot(&self) -> Result<OwnedSemaphorePermit> {
+        // counts this caller as queued until it gets a permit or is cancelled
+        struct Waiting<'a>(&'a AtomicUsize);
+        impl Drop for Waiting<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        self.uploads_waiting.fetch_add(1, Ordering::Relaxed);
+        let _waiting = Waiting(&self.uploads_waiting);
+
+        self.upload_permits
+            .clone()
+            .acquire_owned()
+            .await
+            .context("IPFS upload limiter closed")
+    }
+
+    // uploads waiting for a free slot
+    pub fn upload_queue_depth(&self) -> usize {
+        self.uploads_waiting.load(Ordering::Relaxed)
+    }
+
+    // uploads currently talking to the node
+    pub fn uploads_in_flight(&self) -> usize {
+        self.config.upload_concurrency - self.upload_permits.available_permits()
+    }
+
+    pub fn upload_concurrency(&self) -> usize {
+        self.config.upload_concurrency
+    }
+
+    // more uploads are queued than `DEGRADE_QUEUE_DEPTH` allows
+    pub fn is_degraded(&self) -> bool {
+        self.config
+            .degrade_queue_depth
+            .is_some_and(|threshold| self.upload_queue_depth() > threshold)
+    }
+
+    pub fn degrade_queue_depth(&self) -> Option<usize> {
+        self.config.degrade_queue_depth
+    }
+
+    // Like `send`, but a "not found" style error from the node becomes `None`
+    async fn send_optional(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<Option<reqwest::Response>> {
+        let response = self.transmit(request).await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(Some(response));
+        }
+
+        let error_body = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        if is_not_found(&error_body) {
+            tracing::debug!(body = %error_body, "IPFS API reported missing content");
+            return Ok(None);
+        }
+
+        Err(self.api_error(status, &error_body))
+    }
+
+    // Resolves a DNSLink domain to the CID it currently publishes.
+    //
+    // Resolutions are cached for `dnslink_ttl`; `None` when the domain has no
+    // DNSLink record.
+    pub async fn resolve_dnslink(&self, domain: &str) -> Result<Option<String>> {
+        let domain = domain.trim().trim_end_matches('.').to_lowercase();
+        if let Some(cid) = self.dnslink_cache.get(&domain, self.config.dnslink_ttl) {
+            return Ok(Some(cid));
+        }
+
+        let url = self.config.endpoint("name/resolve")?;
+        let arg = format!("/ipns/{}", domain);
+
+        tracing::debug!(domain = %domain, "Resolving DNSLink via IPFS");
+
+        let request = self.authorize(self.http_client.post(url).query(&[("arg", arg.as_str())]));
+        let Some(response) = self.send_optional(request).await? else {
+            return Ok(None);
+        };
+
+        let resolved: IpfsResolveResponse = response
+            .json()
+            .await
+            .context("Failed to parse IPFS name resolve response")?;
+
+        // "/ipfs/<cid>[/sub/path]" -> "<cid>"
+        let cid = resolved
+            .path
+            .strip_prefix("/ipfs/")
+            .and_then(|rest| rest.split('/').next())
+            .filter(|cid| !cid.is_empty())
+            .with_context(|| format!("Unexpected DNSLink target: {}", resolved.path))?
+            .to_string();
+
+        self.dnslink_cache
+            .insert(&domain, &cid, self.config.dnslink_ttl);
+
+        Ok(Some(cid))
+    }
+
+    // (hits, misses) of the DNSLink cache since startup
+    pub fn dnslink_cache_stats(&self) -> (u64, u64) {
+        (
+            self.dnslink_cache.hits.load(Ordering::Relaxed),
+            self.dnslink_cache.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    // version string the node reports, e.g. "0.32.1"
+    pub async fn version(&self) -> Result<String> {
+        let url = self.config.endpoint("version")?;
+
+        let request = self.authorize(self.http_client.post(url));
+        let response = self.send(request).await?;
+
+        let version: IpfsVersionResponse = response
+            .json()
+            .await
+            .context("Failed to parse IPFS version response")?;
+
+        Ok(version.version)
+    }
+
+    pub fn pin_on_upload(&self) -> bool {
+        self.config.pin_on_upload
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.config.api_url
+    }
+
+    pub fn has_auth(&self) -> bool {
+        self.config.auth != AuthKind::None
+    }
+}
+
+use crate::auth::{AdminToken, RequireAdmin};
+use crate::car::CarFile;
+use crate::dead_letter::{DeadLetterStore, ReplayReport};
+use crate::encryption::FieldEncryptor;
+use crate::schema::{Schema, SchemaRegistry};
+use crate::signature::{HmacSecret, SignedJson};
+use axum::extract::FromRef;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub ipfs_client: Arc<IpfsClient>,
+    pub admin_token: AdminToken,
+    // secret partners sign `/api/v1/ipfs/push` bodies with
+    pub push_secret: HmacSecret,
+    // PII fields encrypted before upload, when a key is configured
+    pub field_encryptor: Option<Arc<FieldEncryptor>>,
+    // failed uploads kept for replay, when DEAD_LETTER_PATH is set
+    pub dead_letters: Option<Arc<DeadLetterStore>>,
+    // JSON Schemas uploads can be validated against
+    pub schemas: Arc<SchemaRegistry>,
+    // `max-age` for reads addressed by CID, which can never change
+    pub immutable_max_age: Duration,
+    // database pool config
+}
+
+impl AppState {
+    pub fn new() -> Result<Self> {
+        let ipfs_client = IpfsClient::from_env()?;
+        Ok(Self {
+            ipfs_client: Arc::new(ipfs_client),
+            admin_token: AdminToken::from_env(),
+            push_secret: HmacSecret::from_env(),
+            field_encryptor: FieldEncryptor::from_env()?.map(Arc::new),
+            dead_letters: DeadLetterStore::from_env().map(Arc::new),
+            schemas: Arc::default(),
+            immutable_max_age: immutable_max_age_from_env()?,
+        })
+    }
+
+    pub fn with_ipfs_client(ipfs_client: IpfsClient) -> Self {
+        Self {
+            ipfs_client: Arc::new(ipfs_client),
+            admin_token: AdminToken::default(),
+            push_secret: HmacSecret::default(),
+            field_encryptor: None,
+            dead_letters: None,
+            schemas: Arc::default(),
+            immutable_max_age: DEFAULT_IMMUTABLE_MAX_AGE,
+        }
+    }
+}
+
+const DEFAULT_IMMUTABLE_MAX_AGE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+//  CACHE_MAX_AGE_SECS
+fn immutable_max_age_from_env() -> Result<Duration> {
+    match env::var("CACHE_MAX_AGE_SECS") {
+        Ok(secs) => Ok(Duration::from_secs(
+            secs.parse()
+                .context("CACHE_MAX_AGE_SECS must be a number of seconds")?,
+        )),
+        Err(_) => Ok(DEFAULT_IMMUTABLE_MAX_AGE),
+    }
+}
+
+impl FromRef<AppState> for AdminToken {
+    fn from_ref(state: &AppState) -> Self {
+        state.admin_token.clone()
+    }
+}
+
+impl FromRef<AppState> for HmacSecret {
+    fn from_ref(state: &AppState) -> Self {
+        state.push_secret.clone()
+    }
 }
 
 
 // all boilerplate pls fix :TODO
-use axum::{extract::State, http::StatusCode, Json};
+use crate::{
+    error::{AppError, JsonBody},
+    negotiate::Format,
+    projection::FieldsQuery,
+};
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{
+        header::{ACCEPT_RANGES, CACHE_CONTROL, CONTENT_RANGE, CONTENT_TYPE, RANGE},
+        HeaderMap, HeaderName, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
 
 #[derive(Debug, Deserialize)]
 pub struct UploadRequest {
     pub data: serde_json::Value,
+    // overrides `IpfsConfig::pin_on_upload` when present
+    #[serde(default)]
+    pub pin: Option<bool>,
+    // registered schema `data` must match
+    #[serde(default)]
+    pub schema_id: Option<String>,
+    // defaults to IPFS_PIN_STRATEGY; ignored when `pin` is false
+    #[serde(default)]
+    pub pin_strategy: Option<PinStrategy>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct UploadResponse {
     pub cid: String,
     pub gateway_url: String,
+    pub deduplicated: bool,
+    pub pinned: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    // outcome of the remote pin, when the strategy asked for one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_pin: Option<RemotePinStatus>,
 }
 
 #[derive(Debug, Serialize)]
-pub struct ErrorResponse {
-    pub error: String,
+pub struct RemotePinStatus {
+    pub service: String,
+    // queued, pinning, pinned or failed
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 // also ????????????????
 pub async fn upload_to_ipfs(
     State(state): State<AppState>,
-    Json(payload): Json<UploadRequest>,
-) -> Result<Json<UploadResponse>, (StatusCode, Json<ErrorResponse>)> {
+    JsonBody(payload): JsonBody<UploadRequest>,
+) -> crate::error::Result<Json<UploadResponse>> {
     tracing::info!("Received IPFS upload request");
 
+    upload(&state, payload).await
+}
+
+// an upload pushed by a partner, its body signed with WEBHOOK_HMAC_SECRET
+pub async fn push_signed(
+    State(state): State<AppState>,
+    SignedJson(payload): SignedJson<UploadRequest>,
+) -> crate::error::Result<Json<UploadResponse>> {
+    tracing::info!("Received signed push");
+
+    upload(&state, payload).await
+}
+
+async fn upload(
+    state: &AppState,
+    payload: UploadRequest,
+) -> crate::error::Result<Json<UploadResponse>> {
+    // An explicit `pin` in the request wins over the configured default
+    let pin = payload.pin.unwrap_or(state.ipfs_client.pin_on_upload());
+    let strategy = payload
+        .pin_strategy
+        .unwrap_or(state.ipfs_client.pin_strategy());
+
+    let remote_service = match state.ipfs_client.remote_pin_service() {
+        Some(service) if pin && strategy.includes_remote() => Some(service.to_string()),
+        None if pin && strategy.includes_remote() => {
+            return Err(AppError::BadRequest(format!(
+                "pin_strategy '{}' needs IPFS_REMOTE_PIN_SERVICE to be configured",
+                strategy
+            )));
+        }
+        _ => None,
+    };
+    let pin_local = pin && strategy.includes_local();
+
+    if let Some(schema_id) = &payload.schema_id {
+        state
+            .schemas
+            .get(&state.ipfs_client, schema_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("No schema with id {}", schema_id)))?
+            .validate(&payload.data)?;
+    }
+
+    let data = match &state.field_encryptor {
+        Some(encryptor) => encryptor
+            .encrypt(payload.data)
+            .map_err(|e| AppError::Unprocessable(e.to_string()))?,
+        None => payload.data,
+    };
+
     // Upload the JSON data to IPFS
-    let cid = state
-        .ipfs_client
-        .upload_json(&payload.data)
-        .await
-        .map_err(|e| {
-            tracing::error!(error = %e, "Failed to upload to IPFS");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to upload to IPFS: {}", e),
-                }),
-            )
-        })?;
+    let IpfsAddResult {
+        cid,
+        deduplicated,
+        pinned,
+        warning,
+    } = match state.ipfs_client.upload_json(&data, pin_local).await {
+        Ok(result) => result,
+        Err(e) => {
+            if let Some(dead_letters) = &state.dead_letters {
+                dead_letters
+                    .record(&state.ipfs_client, data, pin_local, &e)
+                    .await;
+            }
+            return Err(AppError::ipfs("add", None, e));
+        }
+    };
 
     // Construct the gateway URL (using public IPFS gateway)
     let gateway_url = format!("https://ipfs.io/ipfs/{}", cid);
 
     tracing::info!(cid = %cid, "Successfully uploaded to IPFS");
 
-    Ok(Json(UploadResponse { cid, gateway_url }))
+    // the content is stored either way, so a failed remote pin is reported, not fatal
+    let remote_pin = match remote_service {
+        Some(service) => Some(match state.ipfs_client.pin_remote(&cid, &service).await {
+            Ok(remote) => RemotePinStatus {
+                service,
+                status: remote.status,
+                error: None,
+            },
+            Err(e) => {
+                tracing::warn!(cid = %cid, error = %e, "Remote pin failed after upload");
+                RemotePinStatus {
+                    service,
+                    status: "failed".to_string(),
+                    error: Some(e.to_string()),
+                }
+            }
+        }),
+        None => None,
+    };
+
+    Ok(Json(UploadResponse {
+        cid,
+        gateway_url,
+        deduplicated,
+        pinned,
+        warning,
+        remote_pin,
+    }))
 }
 
-use axum::{routing::post, Router};
-pub fn ipfs_router(state: AppState) -> Router {
-    Router::new()
-        .route("/api/ipfs/upload", post(upload_to_ipfs))
-        .with_state(state)
+#[derive(Debug, Serialize)]
+pub struct SchemaResponse {
+    pub schema_id: String,
+}
+
+// store a JSON Schema on IPFS; its CID is the id uploads refer to
+pub async fn register_schema(
+    State(state): State<AppState>,
+    JsonBody(document): JsonBody<serde_json::Value>,
+) -> crate::error::Result<(StatusCode, Json<SchemaResponse>)> {
+    let schema = Schema::compile(document)?;
+    let schema_id = state.schemas.register(&state.ipfs_client, schema).await?;
+
+    Ok((StatusCode::CREATED, Json(SchemaResponse { schema_id })))
+}
+
+pub async fn get_schema(
+    State(state): State<AppState>,
+    Path(schema_id): Path<String>,
+    Query(fields): Query<FieldsQuery>,
+    format: Format,
+) -> crate::error::Result<([(HeaderName, String); 1], Response)> {
+    let schema = state
+        .schemas
+        .get(&state.ipfs_client, &schema_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No schema with id {}", schema_id)))?;
+
+    let cache_control = format!(
+        "public, max-age={}, immutable",
+        state.immutable_max_age.as_secs()
+    );
+
+    Ok((
+        [(CACHE_CONTROL, cache_control)],
+        format.respond(&fields.apply_to(&schema.document)?),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ComputeCidRequest {
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComputeCidResponse {
+    pub cid: String,
+}
+
+// the CID an upload of `data` would get, without touching the node
+pub async fn compute_cid(
+    State(state): State<AppState>,
+    JsonBody(payload): JsonBody<ComputeCidRequest>,
+) -> crate::error::Result<Json<ComputeCidResponse>> {
+    let cid = state
+        .ipfs_client
+        .compute_cid_json(&payload.data)
+        .map_err(|e| AppError::Unprocessable(e.to_string()))?;
+
+    Ok(Json(ComputeCidResponse { cid }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PinQuery {
+    // `PinType::for_size` of the object when not given
+    pub pin_type: Option<PinType>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PinResponse {
+    pub cid: String,
+    pub pin_type: PinType,
+}
+
+// pin an existing CID, admin only; objects over the pin limit are refused,
+// and the pin is direct for single-block objects unless `?pin_type=` says otherwise
+pub async fn pin_cid(
+    _admin: RequireAdmin,
+    State(state): State<AppState>,
+    Path(cid): Path<String>,
+    Query(query): Query<PinQuery>,
+) -> crate::error::Result<Json<PinResponse>> {
+    validate_cid(&cid).map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let stat = state
+        .ipfs_client
+        .stat(&cid)
+        .await
+        .map_err(|e| AppError::ipfs("files/stat", Some(&cid), e))?
+        .ok_or_else(|| AppError::NotFound(format!("CID {} not found on IPFS", cid)))?;
+    if let Some(reason) = state
+        .ipfs_client
+        .check_pin_limit(&cid, stat.cumulative_size)
+    {
+        return Err(AppError::Unprocessable(reason));
+    }
+
+    let pin_type = query
+        .pin_type
+        .unwrap_or_else(|| PinType::for_size(usize::try_from(stat.size).unwrap_or(usize::MAX)));
+    state
+        .ipfs_client
+        .pin(&cid, pin_type)
+        .await
+        .map_err(|e| AppError::ipfs("pin/add", Some(&cid), e))?;
+
+    Ok(Json(PinResponse { cid, pin_type }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemotePinRequest {
+    pub cid: String,
+    // defaults to IPFS_REMOTE_PIN_SERVICE
+    pub service: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RemotePinResponse {
+    pub cid: String,
+    pub service: String,
+    pub status: String,
+}
+
+// hand a CID to a managed pinning service, admin only
+pub async fn pin_remote(
+    _admin: RequireAdmin,
+    State(state): State<AppState>,
+    JsonBody(payload): JsonBody<RemotePinRequest>,
+) -> crate::error::Result<Json<RemotePinResponse>> {
+    validate_cid(&payload.cid).map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let service = payload
+        .service
+        .or_else(|| state.ipfs_client.remote_pin_service().map(str::to_string))
+        .ok_or_else(|| {
+            AppError::BadRequest(
+                "No remote pinning service given and IPFS_REMOTE_PIN_SERVICE is not set"
+                    .to_string(),
+            )
+        })?;
+
+    let pin = state
+        .ipfs_client
+        .pin_remote(&payload.cid, &service)
+        .await
+        .map_err(|e| match e.downcast::<UnknownPinService>() {
+            Ok(unknown) => AppError::Unprocessable(unknown.to_string()),
+            Err(e) => AppError::ipfs("pin/remote/add", Some(&payload.cid), e),
+        })?;
+
+    Ok(Json(RemotePinResponse {
+        cid: payload.cid,
+        service,
+        status: pin.status,
+    }))
+}
+
+// directory uploads carry label images, so allow more than axum's 2 MB default
+const MAX_DIRECTORY_UPLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+// multipart files, each part's file name being its path inside the directory
+pub async fn add_directory(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> crate::error::Result<Json<IpfsDirectoryResult>> {
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::BadRequest(format!("Invalid multipart body: {}", e)))?
+    {
+        let Some(file_name) = field.file_name().map(str::to_owned) else {
+            continue;
+        };
+
+        let path =
+            normalize_dir_path(&file_name).map_err(|e| AppError::BadRequest(e.to_string()))?;
+        if files.iter().any(|(existing, _)| *existing == path) {
+            return Err(AppError::BadRequest(format!(
+                "Duplicate file path '{}'",
+                path
+            )));
+        }
+
+        let bytes = field
+            .bytes()
+            .await
+            .map_err(|e| AppError::BadRequest(format!("Failed to read file '{}': {}", path, e)))?;
+        files.push((path, bytes.to_vec()));
+    }
+
+    if files.is_empty() {
+        return Err(AppError::BadRequest("No files in request".to_string()));
+    }
+
+    let result = state
+        .ipfs_client
+        .add_directory(files)
+        .await
+        .map_err(|e| AppError::ipfs("add", None, e))?;
+
+    Ok(Json(result))
+}
+
+// resolve a CID, optionally following an IPLD path below it
+pub async fn dag_get(
+    State(state): State<AppState>,
+    Path(params): Path<DagPathParams>,
+    Query(fields): Query<FieldsQuery>,
+    format: Format,
+) -> crate::error::Result<([(HeaderName, String); 1], Response)> {
+    validate_cid(&params.cid).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    let path = validate_ipld_path(params.path.as_deref().unwrap_or_default())
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let node = state
+        .ipfs_client
+        .dag_get(&params.cid, &path)
+        .await
+        .map_err(|e| AppError::ipfs("dag/get", Some(&params.cid), e))?
+        .ok_or_else(|| {
+            AppError::NotFound(format!(
+                "No DAG node at {}",
+                [params.cid.as_str(), path.as_str()]
+                    .join("/")
+                    .trim_end_matches('/')
+            ))
+        })?;
+
+    // a CID's content never changes, so CDNs can keep the answer indefinitely
+    let cache_control = format!(
+        "public, max-age={}, immutable",
+        state.immutable_max_age.as_secs()
+    );
+
+    Ok((
+        [(CACHE_CONTROL, cache_control)],
+        format.respond(&fields.apply(node)?),
+    ))
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefsResponse {
+    pub cid: String,
+    pub refs: Vec<String>,
+}
+
+// CIDs an object links to, for walking a DAG one level at a time
+pub async fn list_refs(
+    State(state): State<AppState>,
+    Path(cid): Path<String>,
+    Query(fields): Query<FieldsQuery>,
+    format: Format,
+) -> crate::error::Result<([(HeaderName, String); 1], Response)> {
+    validate_cid(&cid).map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let refs = state
+        .ipfs_client
+        .refs(&cid)
+        .await
+        .map_err(|e| AppError::ipfs("refs", Some(&cid), e))?
+        .ok_or_else(|| AppError::NotFound(format!("No object at {}", cid)))?;
+
+    // links are part of the content, so they can't change either
+    let cache_control = format!(
+        "public, max-age={}, immutable",
+        state.immutable_max_age.as_secs()
+    );
+
+    Ok((
+        [(CACHE_CONTROL, cache_control)],
+        format.respond(&fields.apply_to(&RefsResponse { cid, refs })?),
+    ))
+}
+
+// A `Range` header resolved against an object of known size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    Full,
+    // inclusive bounds
+    Partial { start: u64, end: u64 },
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    // Only a single `bytes=` range is honoured; malformed or multi-range
+    // headers are ignored and the whole object is served, as RFC 9110 allows.
+    pub fn parse(header: Option<&str>, size: u64) -> Self {
+        let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+            return ByteRange::Full;
+        };
+        if spec.contains(',') {
+            return ByteRange::Full;
+        }
+        let Some((start, end)) = spec.trim().split_once('-') else {
+            return ByteRange::Full;
+        };
+
+        let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+            // bytes=-N, the last N bytes
+            (Err(_), Ok(suffix)) if start.is_empty() => {
+                if suffix == 0 || size == 0 {
+                    return ByteRange::Unsatisfiable;
+                }
+                (size.saturating_sub(suffix), size - 1)
+            }
+            // bytes=N-
+            (Ok(start), Err(_)) if end.is_empty() => (start, size.saturating_sub(1)),
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+            _ => return ByteRange::Full,
+        };
+
+        if start >= size {
+            ByteRange::Unsatisfiable
+        } else {
+            ByteRange::Partial { start, end }
+        }
+    }
+}
+
+// Raw bytes of a stored object, as they sit on IPFS (no decompression).
+//
+// Honours `Range` with 206 and `Content-Range`, or 416 when the range starts
+// past the end of the object.
+pub async fn cat_object(
+    State(state): State<AppState>,
+    Path(cid): Path<String>,
+    headers: HeaderMap,
+) -> crate::error::Result<Response> {
+    validate_cid(&cid).map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let stat = state
+        .ipfs_client
+        .stat(&cid)
+        .await
+        .map_err(|e| AppError::ipfs("files/stat", Some(&cid), e))?
+        .ok_or_else(|| AppError::NotFound(format!("No object at {}", cid)))?;
+    let size = stat.size;
+
+    let range = headers.get(RANGE).and_then(|value| value.to_str().ok());
+    let (start, end) = match ByteRange::parse(range, size) {
+        ByteRange::Full => (0, size.checked_sub(1)),
+        ByteRange::Partial { start, end } => (start, Some(end)),
+        ByteRange::Unsatisfiable => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(CONTENT_RANGE, format!("bytes */{}", size))],
+            )
+                .into_response());
+        }
+    };
+
+    let length = end.map_or(0, |end| end - start + 1);
+    let bytes = if length == 0 {
+        Vec::new()
+    } else {
+        state
+            .ipfs_client
+            .cat_range(&cid, start, Some(length))
+            .await
+            .map_err(|e| AppError::ipfs("cat", Some(&cid), e))?
+    };
+
+    let cache_control = format!(
+        "public, max-age={}, immutable",
+        state.immutable_max_age.as_secs()
+    );
+    let mut response = (
+        [
+            (CONTENT_TYPE, "application/octet-stream".to_string()),
+            (ACCEPT_RANGES, "bytes".to_string()),
+            (CACHE_CONTROL, cache_control),
+        ],
+        bytes,
+    )
+        .into_response();
+
+    if range.is_some() && length < size {
+        *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+        if let Ok(content_range) =
+            format!("bytes {}-{}/{}", start, start + length - 1, size).parse()
+        {
+            response.headers_mut().insert(CONTENT_RANGE, content_range);
+        }
+    }
+
+    Ok(response)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DagPathParams {
+    pub cid: String,
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DnslinkQuery {
+    // CID the caller expects the domain to publish
+    pub cid: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DnslinkResponse {
+    pub domain: String,
+    pub cid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<bool>,
+}
+
+// what a DNSLink domain currently points at, optionally checked against `?cid=`
+pub async fn resolve_dnslink(
+    State(state): State<AppState>,
+    Path(domain): Path<String>,
+    Query(query): Query<DnslinkQuery>,
+    Query(fields): Query<FieldsQuery>,
+    format: Format,
+) -> crate::error::Result<([(HeaderName, &'static str); 1], Response)> {
+    validate_domain(&domain).map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let cid = state
+        .ipfs_client
+        .resolve_dnslink(&domain)
+        .await
+        .map_err(|e| AppError::ipfs("name/resolve", None, e))?
+        .ok_or_else(|| AppError::NotFound(format!("No DNSLink record for {}", domain)))?;
+
+    let matches = query.cid.map(|expected| expected == cid);
+
+    // the record can be republished at any time
+    Ok((
+        [(CACHE_CONTROL, "no-cache")],
+        format.respond(&fields.apply_to(&DnslinkResponse {
+            domain,
+            cid,
+            matches,
+        })?),
+    ))
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusResponse {
+    pub service_version: &'static str,
+    pub ipfs: IpfsStatus,
+    pub uploads: UploadStatus,
+    pub dnslink_cache: CacheStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IpfsStatus {
+    pub reachable: bool,
+    pub version: Option<String>,
+    // unix seconds
+    pub last_error_at: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheStatus {
+    pub hits: u64,
+    pub misses: u64,
+    // `None` until the cache has been consulted
+    pub hit_ratio: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadStatus {
+    pub in_flight: usize,
+    pub queued: usize,
+    pub limit: usize,
+    pub degrade_queue_depth: Option<usize>,
+    pub degraded: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadyResponse {
+    pub status: &'static str,
+    pub queued: usize,
+}
+
+// Readiness probe for the load balancer.
+//
+// Answers 503 while the upload queue is deeper than `DEGRADE_QUEUE_DEPTH`,
+// so traffic is shed before requests start timing out, and 200 again once
+// it drains. Without an IPFS client the service can't take uploads at all,
+// so it is never ready.
+pub async fn ready(
+    State(client): State<Option<Arc<IpfsClient>>>,
+) -> (StatusCode, Json<ReadyResponse>) {
+    let Some(client) = client else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ReadyResponse {
+                status: "unavailable",
+                queued: 0,
+            }),
+        );
+    };
+    let queued = client.upload_queue_depth();
+
+    if client.is_degraded() {
+        tracing::warn!(queued, "Upload queue over threshold, reporting degraded");
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ReadyResponse {
+                status: "degraded",
+                queued,
+            }),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(ReadyResponse {
+            status: "ready",
+            queued,
+        }),
+    )
+}
+
+// an uploaded object with its encrypted PII fields restored
+pub async fn decrypt_object(
+    _admin: RequireAdmin,
+    State(state): State<AppState>,
+    Path(cid): Path<String>,
+    Query(fields): Query<FieldsQuery>,
+    format: Format,
+) -> crate::error::Result<Response> {
+    let encryptor = state
+        .field_encryptor
+        .as_ref()
+        .ok_or_else(|| AppError::Forbidden("Field encryption is not configured".to_string()))?;
+    validate_cid(&cid).map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let bytes = state
+        .ipfs_client
+        .get_bytes(&cid)
+        .await
+        .map_err(|e| AppError::ipfs("cat", Some(&cid), e))?
+        .ok_or_else(|| AppError::NotFound(format!("No object at {}", cid)))?;
+    let object: serde_json::Value = serde_json::from_slice(&bytes)
+        .map_err(|_| AppError::Unprocessable(format!("{} is not a JSON object", cid)))?;
+
+    let decrypted = encryptor
+        .decrypt(object)
+        .map_err(|e| AppError::Unprocessable(e.to_string()))?;
+
+    Ok(format.respond(&fields.apply(decrypted)?))
+}
+
+// re-upload everything in the dead-letter store, admin only
+pub async fn replay_dead_letters(
+    _admin: RequireAdmin,
+    State(state): State<AppState>,
+) -> crate::error::Result<Json<ReplayReport>> {
+    let dead_letters = state
+        .dead_letters
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("Dead-letter store is not configured".to_string()))?;
+
+    let report = dead_letters.replay(&state.ipfs_client).await?;
+
+    Ok(Json(report))
+}
+
+// CAR archives from migrations can be far larger than a single upload
+const MAX_CAR_IMPORT_BYTES: usize = 256 * 1024 * 1024;
+
+#[derive(Debug, Serialize)]
+pub struct CarImportReport {
+    pub roots: Vec<ImportedRoot>,
+    pub imported: usize,
+    pub failed: usize,
+    pub failures: Vec<BlockFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockFailure {
+    pub cid: String,
+    pub error: String,
+}
+
+// import a CARv1 archive (the raw request body), admin only.
+//
+// Every block is checked against its CID first; blocks that don't match are
+// left out and reported, the rest are imported and the roots pinned.
+pub async fn import_car(
+    _admin: RequireAdmin,
+    State(state): State<AppState>,
+    body: axum::body::Bytes,
+) -> crate::error::Result<Json<CarImportReport>> {
+    let car = CarFile::parse(&body)
+        .map_err(|e| AppError::BadRequest(format!("Invalid CAR archive: {:#}", e)))?;
+    if car.blocks.is_empty() {
+        return Err(AppError::BadRequest(
+            "CAR archive has no blocks".to_string(),
+        ));
+    }
+
+    let failures: Vec<BlockFailure> = car
+        .blocks
+        .iter()
+        .filter_map(|block| {
+            let error = block.verify().err()?;
+            tracing::warn!(cid = %block.cid, error = %error, "Rejected CAR block");
+            Some(BlockFailure {
+                cid: block.cid.clone(),
+                error: error.to_string(),
+            })
+        })
+        .collect();
+    let verified = car.blocks.len() - failures.len();
+
+    // the count comes from the node, so it reflects what was actually stored
+    let (roots, imported) = if verified == 0 {
+        (Vec::new(), 0)
+    } else {
+        let rejected: HashSet<&str> = failures.iter().map(|f| f.cid.as_str()).collect();
+        let archive = car.encode_filtered(|block| !rejected.contains(block.cid.as_str()));
+        let import = state
+            .ipfs_client
+            .import_car(archive)
+            .await
+            .map_err(|e| AppError::ipfs("dag/import", None, e))?;
+        (import.roots, import.blocks)
+    };
+
+    tracing::info!(
+        roots = ?car.roots,
+        imported,
+        failed = failures.len(),
+        "Imported CAR archive"
+    );
+
+    Ok(Json(CarImportReport {
+        roots,
+        imported,
+        failed: failures.len(),
+        failures,
+    }))
+}
+
+// operational counters in one place, admin only
+pub async fn admin_status(
+    _admin: RequireAdmin,
+    State(state): State<AppState>,
+    Query(fields): Query<FieldsQuery>,
+    format: Format,
+) -> crate::error::Result<Response> {
+    let client = &state.ipfs_client;
+
+    let version = match client.version().await {
+        Ok(version) => Some(version),
+        Err(e) => {
+            tracing::warn!(error = %e, "IPFS node unreachable for status check");
+            None
+        }
+    };
+
+    let (last_error_at, last_error) = match client.last_error() {
+        Some((at, message)) => (
+            at.duration_since(std::time::UNIX_EPOCH)
+                .ok()
+                .map(|since| since.as_secs()),
+            Some(message),
+        ),
+        None => (None, None),
+    };
+
+    let (hits, misses) = client.dnslink_cache_stats();
+
+    let status = StatusResponse {
+        service_version: env!("CARGO_PKG_VERSION"),
+        ipfs: IpfsStatus {
+            reachable: version.is_some(),
+            version,
+            last_error_at,
+            last_error,
+        },
+        uploads: UploadStatus {
+            in_flight: client.uploads_in_flight(),
+            queued: client.upload_queue_depth(),
+            limit: client.upload_concurrency(),
+            degrade_queue_depth: client.degrade_queue_depth(),
+            degraded: client.is_degraded(),
+        },
+        dnslink_cache: CacheStatus {
+            hits,
+            misses,
+            hit_ratio: (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64),
+        },
+    };
+
+    Ok(format.respond(&fields.apply_to(&status)?))
+}
+
+use axum::{
+    routing::{get, post},
+    Router,
+};
+pub fn ipfs_router(state: AppState) -> Router {
+    Router::new()
+        .route("/api/v1/ipfs/upload", post(upload_to_ipfs))
+        // pre-v1 path, kept for existing integrations
+        .route("/api/ipfs/upload", post(upload_to_ipfs))
+        .route("/api/v1/ipfs/push", post(push_signed))
+        .route("/api/v1/ipfs/cid", post(compute_cid))
+        .route("/api/v1/schemas", post(register_schema))
+        .route("/api/v1/schemas/:id", get(get_schema))
+        .route("/api/v1/ipfs/pin/:cid", post(pin_cid))
+        .route("/api/v1/ipfs/pin-remote", post(pin_remote))
+        .route("/api/v1/admin/status", get(admin_status))
+        .route(
+            "/api/v1/admin/dead-letter/replay",
+            post(replay_dead_letters),
+        )
+        .route("/api/v1/admin/ipfs/decrypt/:cid", get(decrypt_object))
+        .route(
+            "/api/v1/admin/import.car",
+            post(import_car).layer(DefaultBodyLimit::max(MAX_CAR_IMPORT_BYTES)),
+        )
+        .route("/api/v1/ipfs/dnslink/:domain", get(resolve_dnslink))
+        .route("/api/v1/ipfs/cat/:cid", get(cat_object))
+        .route("/api/v1/ipfs/dag/:cid", get(dag_get))
+        .route("/api/v1/ipfs/dag/:cid/*path", get(dag_get))
+        .route("/api/v1/ipfs/refs/:cid", get(list_refs))
+        .route(
+            "/api/v1/ipfs/add-dir",
+            post(add_directory).layer(DefaultBodyLimit::max(MAX_DIRECTORY_UPLOAD_BYTES)),
+        )
+        .with_state(state)
+}
+
+// `/ready`, served whether or not IPFS is configured
+pub fn ready_router(client: Option<Arc<IpfsClient>>) -> Router {
+    Router::new().route("/ready", get(ready)).with_state(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(api_url: &str, api_path_prefix: &str) -> IpfsConfig {
+        IpfsConfig {
+            api_url: api_url.to_string(),
+            api_path_prefix: normalize_path_prefix(api_path_prefix),
+            pin_on_upload: true,
+            pipeline: Pipeline::default(),
+            upload_concurrency: DEFAULT_UPLOAD_CONCURRENCY,
+            degrade_queue_depth: None,
+            dnslink_ttl: DEFAULT_DNSLINK_TTL,
+            remote_pin_service: None,
+            pin_strategy: PinStrategy::Local,
+            max_pinned_bytes: DEFAULT_MAX_PINNED_BYTES,
+            cid_version: 1,
+            raw_leaves: true,
+            auth: AuthKind::None,
+        }
+    }
+
+    #[test]
+    fn endpoints_sit_under_the_api_url() {
+        for api_url in ["http://127.0.0.1:5001", "http://127.0.0.1:5001/"] {
+            assert_eq!(
+                config(api_url, "").endpoint("add").unwrap().as_str(),
+                "http://127.0.0.1:5001/api/v0/add"
+            );
+        }
+        assert_eq!(
+            config("https://ipfs.example", "")
+                .endpoint("pin/remote/add")
+                .unwrap()
+                .as_str(),
+            "https://ipfs.example/api/v0/pin/remote/add"
+        );
+    }
+
+    #[test]
+    fn endpoints_include_the_path_prefix() {
+        for prefix in ["ipfs-api", "/ipfs-api", "/ipfs-api/", " ipfs-api/ "] {
+            assert_eq!(
+                config("https://host/", prefix)
+                    .endpoint("add")
+                    .unwrap()
+                    .as_str(),
+                "https://host/ipfs-api/api/v0/add",
+                "{:?}",
+                prefix
+            );
+        }
+        assert_eq!(
+            config("https://host", "proxy/ipfs")
+                .endpoint("files/stat")
+                .unwrap()
+                .as_str(),
+            "https://host/proxy/ipfs/api/v0/files/stat"
+        );
+        assert_eq!(normalize_path_prefix("/"), "");
+    }
+
+    #[test]
+    fn unparseable_api_urls_are_rejected() {
+        assert!(config("not a url", "").endpoint("add").is_err());
+        assert!(config("", "/ipfs-api").validate().is_err());
+        assert!(config("http://127.0.0.1:5001", "/ipfs-api")
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn dir_paths_are_normalized() {
+        assert_eq!(
+            normalize_dir_path("readings/a.json").unwrap(),
+            "readings/a.json"
+        );
+        assert_eq!(
+            normalize_dir_path(" readings/2024/ ").unwrap(),
+            "readings/2024"
+        );
+        assert_eq!(normalize_dir_path("a b/ü.json").unwrap(), "a b/ü.json");
+    }
+
+    #[test]
+    fn dir_paths_cannot_escape_the_directory() {
+        for path in [
+            "",
+            "/",
+            "/etc/passwd",
+            "../secret",
+            "readings/../../secret",
+            "./a.json",
+            "a//b.json",
+            "a\\b.json",
+            "a\nb.json",
+        ] {
+            assert!(normalize_dir_path(path).is_err(), "path {:?}", path);
+        }
+    }
+
+    #[test]
+    fn add_responses_skip_progress_frames() {
+        let body = r#"{"Name":"file","Bytes":262144}
+{"Name":"file","Bytes":300000}
+
+{"Name":"file","Hash":"QmA","Size":"300011"}
+{"Name":"","Hash":"QmRoot","Size":"300100"}
+"#;
+        let entries = parse_add_ndjson(body).unwrap();
+        let hashes: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.hash.as_str()))
+            .collect();
+        assert_eq!(hashes, [("file", "QmA"), ("", "QmRoot")]);
+    }
+
+    #[test]
+    fn add_responses_must_be_json_lines() {
+        assert!(parse_add_ndjson("").unwrap().is_empty());
+        assert!(parse_add_ndjson("not json").is_err());
+        assert!(parse_add_ndjson(r#"{"Hash":"QmA"}"#).is_err());
+    }
+
+    #[test]
+    fn multiaddrs_become_api_urls() {
+        for (addr, url) in [
+            ("/ip4/127.0.0.1/tcp/5001", "http://127.0.0.1:5001"),
+            ("/ip4/10.0.0.2/tcp/5001/http", "http://10.0.0.2:5001"),
+            ("/ip6/::1/tcp/5001", "http://[::1]:5001"),
+            (
+                "/dns/ipfs.internal/tcp/443/https",
+                "https://ipfs.internal:443",
+            ),
+            ("/dns4/kubo/tcp/5001/", "http://kubo:5001"),
+            (" /dns6/kubo/tcp/80 ", "http://kubo:80"),
+        ] {
+            assert_eq!(multiaddr_to_url(addr).unwrap(), url, "{}", addr);
+        }
+    }
+
+    #[test]
+    fn unusable_multiaddrs_are_rejected() {
+        for addr in [
+            "",
+            "/ip4/127.0.0.1",
+            "/ip4/300.0.0.1/tcp/5001",
+            "/ip6/not-an-ip/tcp/5001",
+            "/ip4/127.0.0.1/udp/5001",
+            "/ip4/127.0.0.1/tcp/70000",
+            "/unix/var/run/ipfs.sock",
+            "/ip4/127.0.0.1/tcp/5001/ws",
+            "/ip4/127.0.0.1/tcp/5001/http/extra",
+        ] {
+            assert!(multiaddr_to_url(addr).is_err(), "{}", addr);
+        }
+    }
+
+    fn auth_from(vars: &[(&str, &str)]) -> Result<AuthKind> {
+        AuthKind::from_lookup(|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn auth_kind_follows_the_variables_set() {
+        assert_eq!(auth_from(&[]).unwrap(), AuthKind::None);
+        assert_eq!(
+            auth_from(&[("IPFS_PROJECT_ID", "id"), ("IPFS_PROJECT_SECRET", "secret")]).unwrap(),
+            AuthKind::Basic {
+                id: "id".to_string(),
+                secret: "secret".to_string(),
+            }
+        );
+        assert_eq!(
+            auth_from(&[("IPFS_BEARER_TOKEN", "token")]).unwrap(),
+            AuthKind::Bearer {
+                token: "token".to_string(),
+            }
+        );
+        // empty values count as unset
+        assert_eq!(
+            auth_from(&[("IPFS_BEARER_TOKEN", ""), ("IPFS_PROJECT_ID", "")]).unwrap(),
+            AuthKind::None
+        );
+    }
+
+    #[test]
+    fn auth_kind_rejects_mixed_or_partial_credentials() {
+        assert!(auth_from(&[("IPFS_BEARER_TOKEN", "token"), ("IPFS_PROJECT_ID", "id")]).is_err());
+        // half a Basic pair is ignored with a warning
+        assert_eq!(
+            auth_from(&[("IPFS_PROJECT_ID", "id")]).unwrap(),
+            AuthKind::None
+        );
+    }
+
+    #[test]
+    fn auth_kind_debug_hides_secrets() {
+        let basic = AuthKind::Basic {
+            id: "id".to_string(),
+            secret: "hunter2".to_string(),
+        };
+        let bearer = AuthKind::Bearer {
+            token: "hunter2".to_string(),
+        };
+        assert!(!format!("{:?}", basic).contains("hunter2"));
+        assert!(!format!("{:?}", bearer).contains("hunter2"));
+    }
+
+    #[test]
+    fn byte_ranges_resolve_against_the_size() {
+        let parse = |header| ByteRange::parse(Some(header), 100);
+        assert_eq!(parse("bytes=0-9"), ByteRange::Partial { start: 0, end: 9 });
+        assert_eq!(
+            parse("bytes=90-"),
+            ByteRange::Partial { start: 90, end: 99 }
+        );
+        assert_eq!(
+            parse("bytes=-10"),
+            ByteRange::Partial { start: 90, end: 99 }
+        );
+        // ends past the object are clamped
+        assert_eq!(
+            parse("bytes=50-500"),
+            ByteRange::Partial { start: 50, end: 99 }
+        );
+        assert_eq!(
+            parse("bytes=-500"),
+            ByteRange::Partial { start: 0, end: 99 }
+        );
+        assert_eq!(
+            parse(" bytes=99-99"),
+            ByteRange::Partial { start: 99, end: 99 }
+        );
+    }
+
+    #[test]
+    fn unsatisfiable_byte_ranges() {
+        assert_eq!(
+            ByteRange::parse(Some("bytes=100-"), 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=100-200"), 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=-0"), 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=0-"), 0),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=-5"), 0),
+            ByteRange::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn malformed_or_multi_ranges_serve_everything() {
+        for header in [
+            "bytes=0-1,5-6",
+            "bytes=9-1",
+            "bytes=a-b",
+            "bytes=-",
+            "bytes=5",
+            "items=0-9",
+            "",
+        ] {
+            assert_eq!(
+                ByteRange::parse(Some(header), 100),
+                ByteRange::Full,
+                "{:?}",
+                header
+            );
+        }
+        assert_eq!(ByteRange::parse(None, 100), ByteRange::Full);
+    }
 }
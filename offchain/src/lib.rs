@@ -1,6 +1,18 @@
+pub mod auth;
+pub mod car;
+pub mod cid;
+pub mod compression;
 pub mod config;
+pub mod dead_letter;
+pub mod encryption;
 pub mod error;
 pub mod handlers;
 pub mod ipfs;
+pub mod middleware;
 pub mod models;
+pub mod negotiate;
+pub mod projection;
 pub mod routes;
+pub mod schema;
+pub mod signature;
+pub mod transform;
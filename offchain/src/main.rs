@@ -1,28 +1,51 @@
-use axum::{routing::get, Router};
-use tower_http::cors::CorsLayer;
+use anyhow::Context;
+use axum::{
+    http::HeaderValue,
+    middleware::{from_fn, from_fn_with_state},
+    routing::get,
+    Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use std::net::SocketAddr;
+use tower_http::cors::{Any, CorsLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-mod config;
-mod error;
-mod handlers;
-mod models;
-mod routes;
-
-use config::Config;
+use offchain::{
+    auth::AdminToken,
+    config::{Config, Environment, SafetyPolicy},
+    ipfs::{ipfs_router, ready_router, AppState},
+    middleware::{
+        log_request_bodies, request_timeout, service_headers, sign_responses, BodyRedaction,
+    },
+    routes,
+    signature::{service_key_router, ResponseSigner},
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "offchain=debug,tower_http=debug,axum::rejection=trace".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-
     dotenvy::dotenv().ok();
 
+    // the log format is part of the safety policy, so it's settled before anything logs
+    let json_logs = SafetyPolicy::from_env(&Environment::from_env())?.json_logs;
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "offchain=debug,tower_http=debug,axum::rejection=trace".into());
+    if json_logs {
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(tracing_subscriber::fmt::layer().json())
+            .init();
+    } else {
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(tracing_subscriber::fmt::layer())
+            .init();
+    }
+
     let config = Config::from_env()?;
+    tracing::info!(policy = ?config.safety, "Safety policy");
+    config
+        .safety
+        .check_admin_token(AdminToken::from_env().is_configured())?;
 
     tracing::info!(
         "Starting server in {:?} mode on {}",
@@ -30,22 +53,122 @@ async fn main() -> anyhow::Result<()> {
         config.address()
     );
 
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/", get(root))
         .route("/health", get(health_check))
-        .merge(routes::configure_routes())
-        .layer(CorsLayer::permissive());
+        .merge(routes::configure_routes());
+
+    let ipfs_client = match AppState::new() {
+        Ok(state) => {
+            config
+                .safety
+                .check_ipfs_auth(state.ipfs_client.has_auth())?;
+            let client = state.ipfs_client.clone();
+            app = app.merge(ipfs_router(state));
+            Some(client)
+        }
+        // an environment that requires IPFS credentials can't run without IPFS at all
+        Err(e) if config.safety.require_ipfs_auth => {
+            return Err(e.context(
+                "IPFS must be configured in this environment (or set REQUIRE_IPFS_AUTH=false)",
+            ));
+        }
+        Err(e) => {
+            tracing::warn!("IPFS routes disabled: {:#}", e);
+            None
+        }
+    };
+    app = app.merge(ready_router(ipfs_client));
+
+    if let Some(signer) = ResponseSigner::from_env()? {
+        tracing::info!(key_id = %signer.key_id(), "Signing read responses");
+        app = app
+            .layer(from_fn_with_state(signer.clone(), sign_responses))
+            .merge(service_key_router(signer));
+    }
+
+    app = app.layer(from_fn_with_state(
+        config.handler_timeouts.clone(),
+        request_timeout,
+    ));
+
+    if config.log_bodies {
+        tracing::info!("Logging redacted request bodies");
+        app = app.layer(from_fn_with_state(
+            BodyRedaction::from_env(),
+            log_request_bodies,
+        ));
+    }
+
+    let cors = if config.safety.permissive_cors {
+        CorsLayer::permissive()
+    } else {
+        let origins = config
+            .cors_allowed_origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin)
+                    .with_context(|| format!("Invalid CORS_ALLOWED_ORIGINS entry '{}'", origin))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        tracing::info!(origins = ?config.cors_allowed_origins, "Restricting cross-origin requests");
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(Any)
+            .allow_headers(Any)
+    };
+
+    let app = app.layer(from_fn(service_headers)).layer(cors);
 
     let addr = config.address();
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
 
-    tracing::info!("Server listening on {}", addr);
+    if let Some((cert, key)) = config.tls_paths() {
+        let tls = RustlsConfig::from_pem_file(cert, key).await?;
+        reload_tls_on_sighup(tls.clone(), cert.to_string(), key.to_string());
 
-    axum::serve(listener, app).await?;
+        let addr: SocketAddr = addr.parse()?;
+        tracing::info!("Server listening on https://{}", addr);
+
+        axum_server::bind_rustls(addr, tls)
+            .serve(app.into_make_service())
+            .await?;
+    } else {
+        let listener = tokio::net::TcpListener::bind(&addr).await?;
+
+        tracing::info!("Server listening on {}", addr);
+
+        axum::serve(listener, app).await?;
+    }
 
     Ok(())
 }
 
+// re-read the certificate and key on SIGHUP so renewals don't need a restart
+#[cfg(unix)]
+fn reload_tls_on_sighup(tls: RustlsConfig, cert: String, key: String) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to install SIGHUP handler, TLS reload disabled");
+                return;
+            }
+        };
+
+        while hangups.recv().await.is_some() {
+            match tls.reload_from_pem_file(&cert, &key).await {
+                Ok(()) => tracing::info!("Reloaded TLS certificate"),
+                Err(e) => tracing::error!(error = %e, "Failed to reload TLS certificate"),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn reload_tls_on_sighup(_tls: RustlsConfig, _cert: String, _key: String) {}
+
 async fn root() -> &'static str {
     "Backend API is running"
 }
@@ -0,0 +1,412 @@
+use crate::{
+    config::HandlerTimeouts,
+    error::AppError,
+    signature::{ResponseSigner, SIGNATURE_HEADER, SIGNATURE_KEY_ID_HEADER},
+};
+use axum::{
+    body::{to_bytes, Body, HttpBody},
+    extract::{MatchedPath, Request, State},
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        HeaderName, HeaderValue, Method, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+use std::{env, sync::Arc, time::SystemTime};
+
+// bodies larger than this are passed through without being logged
+const MAX_LOGGED_BODY_BYTES: usize = 64 * 1024;
+// logged text is cut off past this many characters
+const MAX_LOGGED_BODY_CHARS: usize = 4 * 1024;
+const DEFAULT_REDACTED_FIELDS: &str = "email,phone,phone_number,kyc_document_url";
+
+// read responses are JSON documents, anything larger isn't buffered for signing
+const MAX_SIGNED_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+pub const SERVICE_VERSION_HEADER: HeaderName = HeaderName::from_static("x-service-version");
+pub const SERVER_TIME_HEADER: HeaderName = HeaderName::from_static("x-server-time");
+
+// tags every response, errors included, with the build and the time it was served
+pub async fn service_headers(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+
+    let headers = response.headers_mut();
+    headers.insert(
+        SERVICE_VERSION_HEADER,
+        HeaderValue::from_static(env!("CARGO_PKG_VERSION")),
+    );
+    if let Ok(now) = HeaderValue::from_str(&httpdate::fmt_http_date(SystemTime::now())) {
+        headers.insert(SERVER_TIME_HEADER, now);
+    }
+
+    response
+}
+
+// Signs successful GET JSON response bodies with the service key.
+//
+// Other content types, and bodies that may be over `MAX_SIGNED_BODY_BYTES`
+// (streamed ones included), go out unsigned rather than being buffered.
+pub async fn sign_responses(
+    State(signer): State<ResponseSigner>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_read = request.method() == Method::GET;
+    let response = next.run(request).await;
+
+    if !is_read || !response.status().is_success() {
+        return response;
+    }
+
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let small = response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|upper| upper <= MAX_SIGNED_BODY_BYTES as u64);
+
+    if !is_json || !small {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_SIGNED_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to buffer response body for signing");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let (Ok(signature), Ok(key_id)) = (
+        HeaderValue::from_str(&signer.sign(&bytes)),
+        HeaderValue::from_str(signer.key_id()),
+    ) {
+        parts.headers.insert(SIGNATURE_HEADER, signature);
+        parts.headers.insert(SIGNATURE_KEY_ID_HEADER, key_id);
+    }
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+// answers 504 once a request outlives its route's budget; dropping the handler
+// future also aborts any IPFS call it was waiting on
+pub async fn request_timeout(
+    State(timeouts): State<HandlerTimeouts>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request.extensions().get::<MatchedPath>();
+    let Some(budget) = timeouts.for_route(route.map(MatchedPath::as_str)) else {
+        return next.run(request).await;
+    };
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+
+    match tokio::time::timeout(budget, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!(%method, %path, budget_ms = budget.as_millis(), "Request timed out");
+            AppError::GatewayTimeout(format!(
+                "Request did not complete within {} ms",
+                budget.as_millis()
+            ))
+            .into_response()
+        }
+    }
+}
+
+// Which JSON keys `log_request_bodies` masks, at any depth
+#[derive(Debug, Clone)]
+pub struct BodyRedaction {
+    fields: Arc<[String]>,
+}
+
+impl BodyRedaction {
+    pub fn new<S: AsRef<str>>(fields: impl IntoIterator<Item = S>) -> Self {
+        let fields = fields
+            .into_iter()
+            .map(|field| field.as_ref().trim().to_lowercase())
+            .filter(|field| !field.is_empty())
+            .collect();
+        Self { fields }
+    }
+
+    //  LOG_REDACT_FIELDS
+    pub fn from_env() -> Self {
+        Self::from_var("LOG_REDACT_FIELDS")
+    }
+
+    // comma-separated keys from `var`, matched case-insensitively
+    pub fn from_var(var: &str) -> Self {
+        Self::new(
+            env::var(var)
+                .unwrap_or_else(|_| DEFAULT_REDACTED_FIELDS.to_string())
+                .split(','),
+        )
+    }
+
+    pub fn redact(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, child) in map.iter_mut() {
+                    if self.fields.contains(&key.to_lowercase()) {
+                        *child = Value::String("[REDACTED]".to_string());
+                    } else {
+                        self.redact(child);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.redact(item)),
+            _ => {}
+        }
+    }
+}
+
+// Debug logging of JSON request bodies with PII keys masked.
+//
+// Only small `application/json` bodies are buffered; anything else, and any
+// body that doesn't parse, is passed through without being logged.
+pub async fn log_request_bodies(
+    State(redaction): State<BodyRedaction>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_json = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let small = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok())
+        .is_some_and(|length| length <= MAX_LOGGED_BODY_BYTES);
+
+    if !is_json || !small {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = match to_bytes(body, MAX_LOGGED_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to buffer request body for logging");
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+
+    if let Ok(mut value) = serde_json::from_slice::<Value>(&bytes) {
+        redaction.redact(&mut value);
+        let mut logged = value.to_string();
+        if logged.len() > MAX_LOGGED_BODY_CHARS {
+            let cut = (0..=MAX_LOGGED_BODY_CHARS)
+                .rev()
+                .find(|&i| logged.is_char_boundary(i))
+                .unwrap_or(0);
+            logged.truncate(cut);
+            logged.push_str("...");
+        }
+        tracing::debug!(method = %parts.method, path = %parts.uri.path(), body = %logged, "Request body");
+    }
+
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::verify_response_signature;
+    use axum::{
+        middleware::{from_fn, from_fn_with_state},
+        routing::get,
+        Json, Router,
+    };
+    use ed25519_dalek::SigningKey;
+    use serde_json::json;
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    fn signed_app(signer: ResponseSigner) -> Router {
+        Router::new()
+            .route(
+                "/json",
+                get(|| async {
+                    Json(json!({ "cid": "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o" }))
+                })
+                .post(|| async { Json(json!({ "ok": true })) }),
+            )
+            .route(
+                "/bytes",
+                get(|| async {
+                    (
+                        [(CONTENT_TYPE, "application/octet-stream")],
+                        vec![1u8, 2, 3],
+                    )
+                }),
+            )
+            .route(
+                "/large",
+                get(|| async {
+                    let padding = "x".repeat(MAX_SIGNED_BODY_BYTES);
+                    Json(json!({ "padding": padding }))
+                }),
+            )
+            .layer(from_fn_with_state(signer, sign_responses))
+    }
+
+    async fn call(app: Router, method: Method, uri: &str) -> (Response, Vec<u8>) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let bytes = to_bytes(body, usize::MAX).await.unwrap().to_vec();
+        (Response::from_parts(parts, Body::empty()), bytes)
+    }
+
+    #[tokio::test]
+    async fn json_reads_carry_a_signature_that_verifies() {
+        let signer = ResponseSigner::new(SigningKey::from_bytes(&[7; 32]), None);
+        let public_key = hex::encode(signer.verifying_key().as_bytes());
+        let (response, body) = call(signed_app(signer.clone()), Method::GET, "/json").await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[SIGNATURE_KEY_ID_HEADER], signer.key_id());
+        let signature = response.headers()[SIGNATURE_HEADER].to_str().unwrap();
+        assert!(verify_response_signature(&public_key, &body, signature));
+        assert!(!verify_response_signature(&public_key, b"{}", signature));
+    }
+
+    #[tokio::test]
+    async fn non_json_large_and_write_responses_pass_through_unsigned() {
+        let signer = ResponseSigner::new(SigningKey::from_bytes(&[7; 32]), None);
+
+        let (response, body) = call(signed_app(signer.clone()), Method::GET, "/bytes").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body, [1, 2, 3]);
+        assert!(!response.headers().contains_key(SIGNATURE_HEADER));
+
+        let (response, body) = call(signed_app(signer.clone()), Method::GET, "/large").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body.len() > MAX_SIGNED_BODY_BYTES);
+        assert!(!response.headers().contains_key(SIGNATURE_HEADER));
+
+        let (response, _) = call(signed_app(signer), Method::POST, "/json").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(SIGNATURE_HEADER));
+    }
+
+    #[tokio::test]
+    async fn every_response_carries_the_service_headers() {
+        let app = Router::new()
+            .route("/ok", get(|| async { "OK" }))
+            .route(
+                "/err",
+                get(|| async { Err::<(), _>(AppError::BadRequest("Invalid CID".to_string())) }),
+            )
+            .layer(from_fn(service_headers));
+
+        for (uri, status) in [
+            ("/ok", StatusCode::OK),
+            ("/err", StatusCode::BAD_REQUEST),
+            ("/missing", StatusCode::NOT_FOUND),
+        ] {
+            let (response, _) = call(app.clone(), Method::GET, uri).await;
+            assert_eq!(response.status(), status, "{}", uri);
+            assert_eq!(
+                response.headers()[SERVICE_VERSION_HEADER],
+                env!("CARGO_PKG_VERSION")
+            );
+            let served_at = response.headers()[SERVER_TIME_HEADER].to_str().unwrap();
+            assert!(
+                httpdate::parse_http_date(served_at).is_ok(),
+                "{}",
+                served_at
+            );
+        }
+    }
+
+    // log output of a test, see `capture`
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl CapturedLogs {
+        // this thread's logs are written here until the guard is dropped
+        fn capture(&self) -> tracing::subscriber::DefaultGuard {
+            let logs = self.clone();
+            tracing::subscriber::set_default(
+                tracing_subscriber::fmt()
+                    .with_max_level(tracing::Level::DEBUG)
+                    .with_ansi(false)
+                    .with_writer(move || logs.clone())
+                    .finish(),
+            )
+        }
+
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn configured_fields_are_masked_in_logged_bodies() {
+        let logs = CapturedLogs::default();
+        let _guard = logs.capture();
+        let app = Router::new()
+            .route(
+                "/upload",
+                axum::routing::post(|body: String| async { body }),
+            )
+            .layer(from_fn_with_state(
+                BodyRedaction::new(["email", " Phone "]),
+                log_request_bodies,
+            ));
+        let sent = json!({
+            "data": {
+                "name": "Asha",
+                "email": "asha@example.com",
+                "contacts": [{ "PHONE": "+91 98765 43210" }],
+            }
+        })
+        .to_string();
+
+        let request = Request::post("/upload")
+            .header(CONTENT_TYPE, "application/json")
+            .header(CONTENT_LENGTH, sent.len())
+            .body(Body::from(sent.clone()))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        // the handler still sees the body as sent
+        let received = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(received, sent.as_bytes());
+
+        let logged = logs.text();
+        assert!(logged.contains("Request body"));
+        assert!(logged.contains("Asha"));
+        assert!(logged.contains("[REDACTED]"));
+        assert!(!logged.contains("asha@example.com"));
+        assert!(!logged.contains("98765"));
+    }
+}
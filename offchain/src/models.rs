@@ -24,3 +24,118 @@ impl<T> ApiResponse<T> {
         Self { data }
     }
 }
+
+// mean Earth radius used for haversine
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+// GPS position, altitude in meters when known
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GpsCoordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f64>,
+}
+
+impl GpsCoordinates {
+    // great-circle distance in meters; altitude difference is folded in when both points have one
+    pub fn distance_to(&self, other: &GpsCoordinates) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.longitude - self.longitude).to_radians();
+
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        let surface = 2.0 * EARTH_RADIUS_M * a.sqrt().min(1.0).asin();
+
+        match (self.altitude, other.altitude) {
+            (Some(a1), Some(a2)) => surface.hypot(a2 - a1),
+            _ => surface,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (-90.0..=90.0).contains(&self.latitude) && (-180.0..=180.0).contains(&self.longitude)
+    }
+}
+
+// distance request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DistanceRequest {
+    pub from: GpsCoordinates,
+    pub to: GpsCoordinates,
+}
+
+// distance response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DistanceResponse {
+    pub distance_meters: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(latitude: f64, longitude: f64) -> GpsCoordinates {
+        GpsCoordinates {
+            latitude,
+            longitude,
+            altitude: None,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn distance_matches_known_values() {
+        // one degree along a meridian
+        assert_close(
+            point(0.0, 0.0).distance_to(&point(1.0, 0.0)),
+            111_195.0,
+            1.0,
+        );
+        // Paris to London
+        let paris = point(48.8566, 2.3522);
+        let london = point(51.5074, -0.1278);
+        assert_close(paris.distance_to(&london), 343_556.0, 100.0);
+        assert_close(london.distance_to(&paris), paris.distance_to(&london), 1e-6);
+        // antipodes, half the circumference
+        assert_close(
+            point(0.0, 0.0).distance_to(&point(0.0, 180.0)),
+            std::f64::consts::PI * EARTH_RADIUS_M,
+            1.0,
+        );
+        assert_eq!(paris.distance_to(&paris), 0.0);
+    }
+
+    #[test]
+    fn altitude_counts_only_when_both_points_have_one() {
+        let ground = point(10.0, 10.0);
+        let tower = GpsCoordinates {
+            altitude: Some(100.0),
+            ..point(10.0, 10.0)
+        };
+        assert_eq!(ground.distance_to(&tower), 0.0);
+
+        let base = GpsCoordinates {
+            altitude: Some(0.0),
+            ..ground
+        };
+        assert_close(base.distance_to(&tower), 100.0, 1e-9);
+    }
+
+    #[test]
+    fn coordinates_must_be_in_range() {
+        assert!(point(90.0, -180.0).is_valid());
+        assert!(!point(90.1, 0.0).is_valid());
+        assert!(!point(0.0, 180.5).is_valid());
+        assert!(!point(f64::NAN, 0.0).is_valid());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::error::AppError;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{
+        header::{ACCEPT, CONTENT_TYPE, VARY},
+        request::Parts,
+        HeaderValue,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::convert::Infallible;
+
+pub const CBOR_CONTENT_TYPE: &str = "application/cbor";
+
+// Wire format for a read response, picked from the `Accept` header.
+//
+// Only the encoding changes: stored objects and the CIDs computed over them
+// are always JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Json,
+    Cbor,
+}
+
+impl Format {
+    // CBOR when `application/cbor` is accepted and not ranked below `application/json`
+    pub fn from_accept(accept: &str) -> Self {
+        let quality = |media_type: &str| {
+            accept.split(',').find_map(|range| {
+                let mut params = range.split(';').map(str::trim);
+                if !params.next()?.eq_ignore_ascii_case(media_type) {
+                    return None;
+                }
+                let q = params
+                    .find_map(|param| param.strip_prefix("q="))
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                Some(q)
+            })
+        };
+
+        match (quality(CBOR_CONTENT_TYPE), quality("application/json")) {
+            (Some(cbor), json) if cbor > 0.0 && cbor >= json.unwrap_or(0.0) => Format::Cbor,
+            _ => Format::Json,
+        }
+    }
+
+    pub fn respond<T: Serialize>(self, value: &T) -> Response {
+        let mut response = match self {
+            Format::Json => Json(value).into_response(),
+            Format::Cbor => {
+                let mut body = Vec::new();
+                if let Err(e) = ciborium::into_writer(value, &mut body) {
+                    tracing::error!(error = %e, "Failed to encode response as CBOR");
+                    return AppError::Internal("Failed to encode response".to_string())
+                        .into_response();
+                }
+                ([(CONTENT_TYPE, CBOR_CONTENT_TYPE)], body).into_response()
+            }
+        };
+
+        response
+            .headers_mut()
+            .append(VARY, HeaderValue::from_static("accept"));
+        response
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .headers
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .map(Format::from_accept)
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn json_is_the_default() {
+        assert_eq!(Format::from_accept(""), Format::Json);
+        assert_eq!(Format::from_accept("*/*"), Format::Json);
+        assert_eq!(Format::from_accept("application/json"), Format::Json);
+    }
+
+    #[test]
+    fn cbor_is_picked_when_accepted() {
+        assert_eq!(Format::from_accept("application/cbor"), Format::Cbor);
+        assert_eq!(Format::from_accept("Application/CBOR"), Format::Cbor);
+        // a tie goes to CBOR, the client listed it
+        assert_eq!(
+            Format::from_accept("application/json, application/cbor"),
+            Format::Cbor
+        );
+    }
+
+    #[test]
+    fn quality_values_are_honoured() {
+        assert_eq!(
+            Format::from_accept("application/json, application/cbor;q=0.5"),
+            Format::Json
+        );
+        assert_eq!(
+            Format::from_accept("application/json;q=0.2, application/cbor;q=0.8"),
+            Format::Cbor
+        );
+        assert_eq!(Format::from_accept("application/cbor;q=0"), Format::Json);
+    }
+
+    #[tokio::test]
+    async fn cbor_responses_decode_to_the_same_value() {
+        let value = json!({ "cid": "QmA", "refs": ["QmB", "QmC"], "size": 7 });
+
+        let response = Format::Cbor.respond(&value);
+
+        assert_eq!(response.headers()[CONTENT_TYPE], CBOR_CONTENT_TYPE);
+        assert_eq!(response.headers()[VARY], "accept");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let decoded: Value = ciborium::from_reader(body.as_ref()).unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn json_responses_also_vary_on_accept() {
+        let response = Format::Json.respond(&json!({}));
+
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(response.headers()[VARY], "accept");
+    }
+}
@@ -0,0 +1,185 @@
+use crate::error::{AppError, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// `?fields=a.b,c` on read endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct FieldsQuery {
+    pub fields: Option<String>,
+}
+
+impl FieldsQuery {
+    // projects `value` when `fields` was given, otherwise returns it untouched
+    pub fn apply(&self, value: Value) -> Result<Value> {
+        match self.fields.as_deref() {
+            Some(fields) => {
+                let paths: Vec<&str> = fields.split(',').map(str::trim).collect();
+                project(&value, &paths)
+            }
+            None => Ok(value),
+        }
+    }
+
+    // `apply` for a typed response body
+    pub fn apply_to<T: Serialize>(&self, body: &T) -> Result<Value> {
+        let value = serde_json::to_value(body)
+            .map_err(|e| AppError::Internal(format!("Failed to serialize response: {}", e)))?;
+        self.apply(value)
+    }
+}
+
+// Keeps only the given dotted paths of `value`, preserving their nesting.
+//
+// A path segment applied to an array is applied to each element, so
+// `records.cid` picks the `cid` of every record. Empty segments or a path
+// that matches nothing are rejected as a bad request.
+pub fn project(value: &Value, paths: &[&str]) -> Result<Value> {
+    let mut projected = Value::Object(Map::new());
+
+    for path in paths {
+        let segments: Vec<&str> = path.split('.').collect();
+        if segments.iter().any(|segment| segment.is_empty()) {
+            return Err(AppError::BadRequest(format!(
+                "Invalid field path: '{}'",
+                path
+            )));
+        }
+
+        let picked = pick(value, &segments)
+            .ok_or_else(|| AppError::BadRequest(format!("Unknown field path: '{}'", path)))?;
+        merge(&mut projected, picked);
+    }
+
+    Ok(projected)
+}
+
+fn pick(value: &Value, segments: &[&str]) -> Option<Value> {
+    let Some((head, rest)) = segments.split_first() else {
+        return Some(value.clone());
+    };
+
+    match value {
+        Value::Object(map) => {
+            let child = pick(map.get(*head)?, rest)?;
+            let mut out = Map::new();
+            out.insert((*head).to_string(), child);
+            Some(Value::Object(out))
+        }
+        Value::Array(items) => {
+            let picked: Vec<Value> = items
+                .iter()
+                .map(|item| pick(item, segments).unwrap_or(Value::Null))
+                .collect();
+            if picked.iter().all(Value::is_null) {
+                None
+            } else {
+                Some(Value::Array(picked))
+            }
+        }
+        _ => None,
+    }
+}
+
+// deep-merges two projections of the same source value
+fn merge(target: &mut Value, source: Value) {
+    match (target, source) {
+        // array element that this path didn't match
+        (_, Value::Null) => {}
+        (Value::Object(target), Value::Object(source)) => {
+            for (key, value) in source {
+                match target.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(target), Value::Array(source)) => {
+            for (existing, value) in target.iter_mut().zip(source) {
+                merge(existing, value);
+            }
+        }
+        (target, source) => *target = source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record() -> Value {
+        json!({
+            "id": 7,
+            "sensor": { "name": "wh-1", "location": { "lat": 1.5, "lon": 2.5 } },
+            "records": [
+                { "cid": "QmA", "size": 10 },
+                { "cid": "QmB", "size": 20 },
+                { "note": "no cid" }
+            ]
+        })
+    }
+
+    #[test]
+    fn keeps_nesting_of_dotted_paths() {
+        let projected = project(&record(), &["id", "sensor.location.lat"]).unwrap();
+        assert_eq!(
+            projected,
+            json!({ "id": 7, "sensor": { "location": { "lat": 1.5 } } })
+        );
+    }
+
+    #[test]
+    fn paths_into_arrays_apply_to_each_element() {
+        let projected = project(&record(), &["records.cid"]).unwrap();
+        assert_eq!(
+            projected,
+            json!({ "records": [{ "cid": "QmA" }, { "cid": "QmB" }, null] })
+        );
+    }
+
+    #[test]
+    fn overlapping_paths_are_merged() {
+        let projected = project(
+            &record(),
+            &[
+                "sensor.name",
+                "sensor.location.lon",
+                "records.size",
+                "records.cid",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            projected,
+            json!({
+                "sensor": { "name": "wh-1", "location": { "lon": 2.5 } },
+                "records": [{ "size": 10, "cid": "QmA" }, { "size": 20, "cid": "QmB" }, null]
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_and_malformed_paths_are_bad_requests() {
+        for path in ["missing", "sensor.missing", "id.deeper", "sensor..name", ""] {
+            assert!(
+                matches!(project(&record(), &[path]), Err(AppError::BadRequest(_))),
+                "path {:?}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn fields_query_is_optional() {
+        assert_eq!(FieldsQuery::default().apply(record()).unwrap(), record());
+        let query = FieldsQuery {
+            fields: Some("id, sensor.name".to_string()),
+        };
+        assert_eq!(
+            query.apply(record()).unwrap(),
+            json!({ "id": 7, "sensor": { "name": "wh-1" } })
+        );
+    }
+}
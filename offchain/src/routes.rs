@@ -1,12 +1,10 @@
 use crate::handlers;
-use axum::{
-    routing::{get, post},
-    Router,
-};
+use axum::{routing::post, Router};
 
 pub fn configure_routes() -> Router {
     Router::new()
-    // .route("/api/example/:id", get(handlers::get_example))
-    // .route("/api/example", post(handlers::post_example))
-    // .route("/api/error", get(handlers::error_example))
+        // .route("/api/example/:id", get(handlers::get_example))
+        // .route("/api/example", post(handlers::post_example))
+        // .route("/api/error", get(handlers::error_example))
+        .route("/api/v1/geo/distance", post(handlers::geo_distance))
 }
@@ -0,0 +1,215 @@
+use crate::{
+    error::{AppError, Result},
+    ipfs::{validate_cid, IpfsClient},
+};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+// compiled schemas kept in memory; CIDs never change so entries never go stale
+const MAX_CACHED_SCHEMAS: usize = 256;
+
+// errors listed in a 422 before the rest are summarised
+const MAX_REPORTED_ERRORS: usize = 10;
+
+// A JSON Schema together with its compiled validator
+#[derive(Debug)]
+pub struct Schema {
+    pub document: Value,
+    validator: jsonschema::Validator,
+}
+
+impl Schema {
+    // rejects documents that aren't a valid JSON Schema
+    pub fn compile(document: Value) -> Result<Self> {
+        let validator = jsonschema::validator_for(&document)
+            .map_err(|e| AppError::Unprocessable(format!("Invalid JSON Schema: {}", e)))?;
+        Ok(Self {
+            document,
+            validator,
+        })
+    }
+
+    // 422 listing where `instance` breaks the schema
+    pub fn validate(&self, instance: &Value) -> Result<()> {
+        let errors: Vec<String> = self
+            .validator
+            .iter_errors(instance)
+            .map(|e| format!("{}: {}", e.instance_path, e))
+            .collect();
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        let mut message = errors
+            .iter()
+            .take(MAX_REPORTED_ERRORS)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("; ");
+        if errors.len() > MAX_REPORTED_ERRORS {
+            message.push_str(&format!(
+                "; and {} more",
+                errors.len() - MAX_REPORTED_ERRORS
+            ));
+        }
+        Err(AppError::Unprocessable(format!(
+            "Data does not match schema: {}",
+            message
+        )))
+    }
+}
+
+// Compiled schemas by id, dropping the least recently used one when full
+#[derive(Debug)]
+struct SchemaCache {
+    // id -> (schema, tick of its last use)
+    entries: HashMap<String, (Arc<Schema>, u64)>,
+    tick: u64,
+    capacity: usize,
+}
+
+impl SchemaCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            tick: 0,
+            capacity,
+        }
+    }
+
+    fn get(&mut self, id: &str) -> Option<Arc<Schema>> {
+        self.tick += 1;
+        let (schema, last_used) = self.entries.get_mut(id)?;
+        *last_used = self.tick;
+        Some(schema.clone())
+    }
+
+    fn insert(&mut self, id: &str, schema: Arc<Schema>) {
+        self.tick += 1;
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(id) {
+            // a linear scan is fine at this size, and only runs once the cache is full
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(id.to_string(), (schema, self.tick));
+    }
+}
+
+impl Default for SchemaCache {
+    fn default() -> Self {
+        Self::new(MAX_CACHED_SCHEMAS)
+    }
+}
+
+// Schemas stored on IPFS and addressed by their CID (the schema id)
+#[derive(Debug, Default)]
+pub struct SchemaRegistry {
+    cache: Mutex<SchemaCache>,
+}
+
+impl SchemaRegistry {
+    // uploads and pins a compiled schema, returning its id
+    pub async fn register(&self, client: &IpfsClient, schema: Schema) -> Result<String> {
+        let result = client
+            .upload_json(&schema.document, true)
+            .await
+            .map_err(|e| AppError::ipfs("add", None, e))?;
+
+        tracing::info!(schema_id = %result.cid, "Registered JSON Schema");
+
+        self.insert(&result.cid, Arc::new(schema));
+        Ok(result.cid)
+    }
+
+    // the schema stored under `id`; `None` when the node doesn't have it
+    pub async fn get(&self, client: &IpfsClient, id: &str) -> Result<Option<Arc<Schema>>> {
+        validate_cid(id).map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+        if let Some(schema) = self.cache.lock().ok().and_then(|mut cache| cache.get(id)) {
+            return Ok(Some(schema));
+        }
+
+        let Some(bytes) = client
+            .get_bytes(id)
+            .await
+            .map_err(|e| AppError::ipfs("cat", Some(id), e))?
+        else {
+            return Ok(None);
+        };
+
+        let document: Value = serde_json::from_slice(&bytes)
+            .map_err(|_| AppError::Unprocessable(format!("{} is not a JSON Schema", id)))?;
+        let schema = Arc::new(Schema::compile(document)?);
+
+        self.insert(id, schema.clone());
+        Ok(Some(schema))
+    }
+
+    fn insert(&self, id: &str, schema: Arc<Schema>) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(id, schema);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Arc<Schema> {
+        Arc::new(Schema::compile(json!({ "type": "object" })).unwrap())
+    }
+
+    #[test]
+    fn the_least_recently_used_schema_is_evicted() {
+        let mut cache = SchemaCache::new(2);
+        cache.insert("a", schema());
+        cache.insert("b", schema());
+        // reading `a` makes `b` the oldest
+        assert!(cache.get("a").is_some());
+
+        cache.insert("c", schema());
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn replacing_a_cached_schema_evicts_nothing() {
+        let mut cache = SchemaCache::new(2);
+        cache.insert("a", schema());
+        cache.insert("b", schema());
+
+        cache.insert("a", schema());
+
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.get("b").is_some());
+    }
+
+    #[test]
+    fn invalid_instances_are_unprocessable() {
+        let schema = Schema::compile(json!({
+            "type": "object",
+            "required": ["sensor"],
+            "properties": { "sensor": { "type": "string" } },
+        }))
+        .unwrap();
+
+        assert!(schema.validate(&json!({ "sensor": "wh-1" })).is_ok());
+        assert!(matches!(
+            schema.validate(&json!({ "sensor": 7 })),
+            Err(AppError::Unprocessable(_))
+        ));
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{error::AppError, negotiate::Format};
+use anyhow::Context;
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRef, FromRequest, Request, State},
+    http::StatusCode,
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use hmac::{Hmac, Mac};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use std::{env, sync::Arc};
+
+pub const SIGNATURE_HEADER: &str = "x-signature";
+pub const SIGNATURE_KEY_ID_HEADER: &str = "x-signature-key-id";
+
+type HmacSha256 = Hmac<Sha256>;
+
+// shared secret partners sign pushed bodies with; `None` keeps signed pushes closed
+#[derive(Clone, Default)]
+pub struct HmacSecret(Option<Arc<[u8]>>);
+
+impl HmacSecret {
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self(Some(secret.as_ref().into()))
+    }
+
+    //  WEBHOOK_HMAC_SECRET
+    pub fn from_env() -> Self {
+        env::var("WEBHOOK_HMAC_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .map(Self::new)
+            .unwrap_or_default()
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.0.is_some()
+    }
+
+    fn mac(&self) -> Option<HmacSha256> {
+        let secret = self.0.as_ref()?;
+        Some(HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any size"))
+    }
+
+    // hex HMAC-SHA256 of `body`; `None` when no secret is configured
+    pub fn sign(&self, body: &[u8]) -> Option<String> {
+        let mut mac = self.mac()?;
+        mac.update(body);
+        Some(hex::encode(mac.finalize().into_bytes()))
+    }
+
+    // constant-time check of a hex signature, optionally prefixed with `sha256=`
+    pub fn verify(&self, body: &[u8], signature: &str) -> bool {
+        let signature = signature.trim();
+        let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+        let (Some(mut mac), Ok(expected)) = (self.mac(), hex::decode(signature)) else {
+            return false;
+        };
+
+        mac.update(body);
+        mac.verify_slice(&expected).is_ok()
+    }
+}
+
+impl std::fmt::Debug for HmacSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(if self.0.is_some() {
+            "HmacSecret(..)"
+        } else {
+            "HmacSecret(None)"
+        })
+    }
+}
+
+// JSON body whose raw bytes were checked against `X-Signature` before parsing.
+//
+// The state provides the `HmacSecret` via `FromRef`. Without a configured
+// secret every request is 403, a missing or mismatched signature is 401, and
+// the body is only deserialized once the signature over the exact received
+// bytes checks out.
+#[derive(Debug)]
+pub struct SignedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for SignedJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+    HmacSecret: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let secret = HmacSecret::from_ref(state);
+        if !secret.is_configured() {
+            return Err(AppError::Forbidden(
+                "Signed pushes are disabled, set WEBHOOK_HMAC_SECRET".to_string(),
+            ));
+        }
+
+        let signature = req
+            .headers()
+            .get(SIGNATURE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+            .ok_or_else(|| AppError::Unauthorized("Missing X-Signature header".to_string()))?;
+
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| match e.status() {
+                StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge(e.body_text()),
+                _ => AppError::BadRequest(format!("Failed to read request body: {}", e)),
+            })?;
+
+        if !secret.verify(&body, &signature) {
+            tracing::warn!("Rejected push with an invalid HMAC signature");
+            return Err(AppError::Unauthorized("Invalid signature".to_string()));
+        }
+
+        let Json(value) = Json::<T>::from_bytes(&body)?;
+        Ok(SignedJson(value))
+    }
+}
+
+// Ed25519 key the service signs its read responses with
+#[derive(Clone)]
+pub struct ResponseSigner {
+    signing_key: Arc<SigningKey>,
+    key_id: String,
+}
+
+impl ResponseSigner {
+    pub fn new(signing_key: SigningKey, key_id: Option<String>) -> Self {
+        // default id: first 8 bytes of sha256(public key), stable for a given key
+        let key_id = key_id.unwrap_or_else(|| {
+            let digest = Sha256::digest(signing_key.verifying_key().as_bytes());
+            hex::encode(&digest[..8])
+        });
+
+        Self {
+            signing_key: Arc::new(signing_key),
+            key_id,
+        }
+    }
+
+    //  SERVICE_SIGNING_KEY (hex 32-byte seed), SERVICE_KEY_ID
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(seed) = env::var("SERVICE_SIGNING_KEY")
+            .ok()
+            .filter(|seed| !seed.is_empty())
+        else {
+            return Ok(None);
+        };
+
+        let seed: [u8; 32] = hex::decode(seed.trim())
+            .context("SERVICE_SIGNING_KEY must be hex")?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("SERVICE_SIGNING_KEY must be a 32-byte seed"))?;
+        let key_id = env::var("SERVICE_KEY_ID").ok().filter(|id| !id.is_empty());
+
+        Ok(Some(Self::new(SigningKey::from_bytes(&seed), key_id)))
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.signing_key.verifying_key()
+    }
+
+    // hex Ed25519 signature over the exact body bytes
+    pub fn sign(&self, body: &[u8]) -> String {
+        hex::encode(self.signing_key.sign(body).to_bytes())
+    }
+}
+
+impl std::fmt::Debug for ResponseSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseSigner")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+// Checks an `X-Signature` value against the published hex public key.
+//
+// The signature covers the response body exactly as received, so clients
+// must verify the raw bytes before re-serializing anything.
+pub fn verify_response_signature(public_key: &str, body: &[u8], signature: &str) -> bool {
+    let Some(public_key) = hex::decode(public_key)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+    else {
+        return false;
+    };
+    let Some(signature) = hex::decode(signature)
+        .ok()
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .map(|bytes| Signature::from_bytes(&bytes))
+    else {
+        return false;
+    };
+
+    public_key.verify(body, &signature).is_ok()
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServiceKeyResponse {
+    pub key_id: String,
+    pub algorithm: &'static str,
+    pub public_key: String,
+}
+
+// public half of the response signing key
+pub async fn service_key(State(signer): State<ResponseSigner>, format: Format) -> Response {
+    format.respond(&ServiceKeyResponse {
+        key_id: signer.key_id().to_string(),
+        algorithm: "ed25519",
+        public_key: hex::encode(signer.verifying_key().as_bytes()),
+    })
+}
+
+pub fn service_key_router(signer: ResponseSigner) -> Router {
+    Router::new()
+        .route("/.well-known/service-key", get(service_key))
+        .with_state(signer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = br#"{"data":{"sensor":"wh-1"}}"#;
+
+    #[test]
+    fn hmac_signatures_verify_over_the_exact_body() {
+        let secret = HmacSecret::new("partner-secret");
+        let signature = secret.sign(BODY).unwrap();
+
+        assert!(secret.verify(BODY, &signature));
+        assert!(secret.verify(BODY, &format!("sha256={}", signature)));
+        assert!(secret.verify(BODY, &signature.to_uppercase()));
+        assert!(!secret.verify(br#"{"data":{"sensor":"wh-2"}}"#, &signature));
+        assert!(!HmacSecret::new("other-secret").verify(BODY, &signature));
+    }
+
+    #[test]
+    fn malformed_or_unconfigured_signatures_never_verify() {
+        let secret = HmacSecret::new("partner-secret");
+        assert!(!secret.verify(BODY, ""));
+        assert!(!secret.verify(BODY, "not hex"));
+        assert!(!secret.verify(BODY, "abcd"));
+
+        let unconfigured = HmacSecret::default();
+        assert!(unconfigured.sign(BODY).is_none());
+        assert!(!unconfigured.verify(BODY, &secret.sign(BODY).unwrap()));
+    }
+}
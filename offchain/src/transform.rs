@@ -0,0 +1,290 @@
+use crate::{
+    compression::Compression,
+    encryption::{key_from_env, SealingKey},
+    middleware::BodyRedaction,
+};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::{fmt, sync::Arc};
+
+// prefix of an encrypted object, after PNG's trick of a high first byte
+const ENCRYPTED_MAGIC: &[u8; 4] = b"\x89ENC";
+
+// One step applied to an object's bytes before upload.
+//
+// A reversible transform marks its output (see `Compression`) so `invert`
+// can tell what it wrote and pass anything else through untouched; that way
+// objects written under an older pipeline still read back.
+pub trait Transform: Send + Sync {
+    // the name used in IPFS_PIPELINE
+    fn name(&self) -> &'static str;
+
+    fn apply(&self, bytes: Vec<u8>) -> Result<Vec<u8>>;
+
+    // undoes `apply`; lossy transforms return their input
+    fn invert(&self, bytes: Vec<u8>) -> Result<Vec<u8>>;
+
+    // whether the same input always gives the same output, which local CIDs
+    // and upload dedup rely on
+    fn is_deterministic(&self) -> bool {
+        true
+    }
+}
+
+impl Transform for Compression {
+    fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    fn apply(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        self.compress(bytes)
+    }
+
+    fn invert(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        if Compression::detect(&bytes) == *self {
+            self.decompress(bytes)
+        } else {
+            Ok(bytes)
+        }
+    }
+}
+
+// Masks PII keys in JSON objects for deployments that must never store them.
+// The originals are gone for good, so reads get the masked object; bytes
+// that aren't JSON pass through.
+pub struct Redact(pub BodyRedaction);
+
+impl Transform for Redact {
+    fn name(&self) -> &'static str {
+        "redact"
+    }
+
+    fn apply(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let Ok(mut value) = serde_json::from_slice::<Value>(&bytes) else {
+            return Ok(bytes);
+        };
+        self.0.redact(&mut value);
+        serde_json::to_vec(&value).context("Failed to serialize redacted JSON")
+    }
+
+    fn invert(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(bytes)
+    }
+}
+
+// Whole-object AES-256-GCM: `\x89ENC || nonce || ciphertext`, keyed by
+// IPFS_ENCRYPTION_KEY (see `SealingKey`). A fresh nonce per upload means the
+// same object gets a different CID each time.
+pub struct Encrypt {
+    key: SealingKey,
+}
+
+impl Encrypt {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            key: SealingKey::new(key),
+        }
+    }
+}
+
+impl Transform for Encrypt {
+    fn name(&self) -> &'static str {
+        "encrypt"
+    }
+
+    fn apply(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let sealed = self.key.seal(&bytes).context("Failed to encrypt object")?;
+
+        let mut out = ENCRYPTED_MAGIC.to_vec();
+        out.extend(sealed);
+        Ok(out)
+    }
+
+    fn invert(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let Some(rest) = bytes.strip_prefix(ENCRYPTED_MAGIC) else {
+            return Ok(bytes);
+        };
+        self.key
+            .open(rest)
+            .context("Failed to decrypt object, wrong IPFS_ENCRYPTION_KEY?")
+    }
+
+    fn is_deterministic(&self) -> bool {
+        false
+    }
+}
+
+// The transforms run on every upload, in order, and undone in reverse on reads
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    transforms: Arc<[Box<dyn Transform>]>,
+}
+
+impl Pipeline {
+    pub fn new(transforms: Vec<Box<dyn Transform>>) -> Self {
+        Self {
+            transforms: transforms.into(),
+        }
+    }
+
+    // Builds a pipeline from comma-separated names, e.g. `redact,gzip,encrypt`.
+    //
+    // `redact` masks the keys in IPFS_REDACT_FIELDS, `encrypt` needs
+    // IPFS_ENCRYPTION_KEY (hex, 32 bytes).
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut transforms: Vec<Box<dyn Transform>> = Vec::new();
+        for name in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let transform: Box<dyn Transform> = match name.to_lowercase().as_str() {
+                "redact" => Box::new(Redact(BodyRedaction::from_var("IPFS_REDACT_FIELDS"))),
+                "encrypt" => Box::new(Encrypt::new(
+                    &key_from_env("IPFS_ENCRYPTION_KEY")?
+                        .context("The encrypt transform needs IPFS_ENCRYPTION_KEY")?,
+                )),
+                codec => match codec.parse::<Compression>() {
+                    Ok(Compression::None) => continue,
+                    Ok(compression) => Box::new(compression),
+                    Err(_) => anyhow::bail!(
+                        "Unknown transform '{}', expected redact, gzip, zstd or encrypt",
+                        name
+                    ),
+                },
+            };
+            transforms.push(transform);
+        }
+        Ok(Self::new(transforms))
+    }
+
+    pub fn apply(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        self.transforms.iter().try_fold(bytes, |bytes, transform| {
+            transform
+                .apply(bytes)
+                .with_context(|| format!("Transform '{}' failed", transform.name()))
+        })
+    }
+
+    pub fn invert(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        self.transforms
+            .iter()
+            .rev()
+            .try_fold(bytes, |bytes, transform| {
+                transform
+                    .invert(bytes)
+                    .with_context(|| format!("Undoing transform '{}' failed", transform.name()))
+            })
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        self.transforms
+            .iter()
+            .all(|transform| transform.is_deterministic())
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.transforms
+            .iter()
+            .map(|transform| transform.name())
+            .collect()
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Pipeline").field(&self.names()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const READING: &[u8] = br#"{"sensor":"wh-1","email":"ops@example.com","temperature":21.5}"#;
+
+    fn pipeline(transforms: Vec<Box<dyn Transform>>) -> Pipeline {
+        Pipeline::new(transforms)
+    }
+
+    fn encrypt() -> Box<dyn Transform> {
+        Box::new(Encrypt::new(&[7; 32]))
+    }
+
+    #[test]
+    fn pipelines_round_trip() {
+        let pipelines = [
+            pipeline(vec![]),
+            pipeline(vec![Box::new(Compression::Gzip)]),
+            pipeline(vec![Box::new(Compression::Zstd)]),
+            pipeline(vec![encrypt()]),
+            pipeline(vec![Box::new(Compression::Gzip), encrypt()]),
+            pipeline(vec![Box::new(Compression::Zstd), encrypt()]),
+        ];
+        for pipeline in pipelines {
+            let stored = pipeline.apply(READING.to_vec()).unwrap();
+            assert_eq!(pipeline.invert(stored).unwrap(), READING, "{:?}", pipeline);
+        }
+    }
+
+    #[test]
+    fn arbitrary_leading_bytes_survive_the_round_trip() {
+        let gzip = pipeline(vec![Box::new(Compression::Gzip)]);
+        let gzipped_file = Compression::Gzip.compress(READING.to_vec()).unwrap();
+        let zstd_file = Compression::Zstd.compress(READING.to_vec()).unwrap();
+        for input in [
+            vec![0x02, 9, 9],
+            vec![0x01],
+            vec![0x03, 1, 2, 3],
+            gzipped_file,
+            zstd_file,
+            ENCRYPTED_MAGIC.to_vec(),
+        ] {
+            let stored = gzip.apply(input.clone()).unwrap();
+            assert_eq!(gzip.invert(stored).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn transforms_leave_other_output_alone() {
+        let zstd = Compression::Zstd.compress(READING.to_vec()).unwrap();
+        assert_eq!(Compression::Gzip.invert(zstd.clone()).unwrap(), zstd);
+        assert_eq!(Compression::Gzip.invert(READING.to_vec()).unwrap(), READING);
+        assert_eq!(encrypt().invert(READING.to_vec()).unwrap(), READING);
+    }
+
+    #[test]
+    fn redaction_is_not_undone() {
+        // unset, so the default fields (email among them) are masked
+        let fields = BodyRedaction::from_var("TRANSFORM_TEST_REDACT_FIELDS");
+        let redact = pipeline(vec![Box::new(Redact(fields)), Box::new(Compression::Gzip)]);
+        let stored = redact.apply(READING.to_vec()).unwrap();
+        let read: Value = serde_json::from_slice(&redact.invert(stored).unwrap()).unwrap();
+        assert_ne!(read["email"], "ops@example.com");
+        assert_eq!(read["sensor"], "wh-1");
+    }
+
+    #[test]
+    fn encryption_is_not_deterministic() {
+        let encrypted = pipeline(vec![Box::new(Compression::Gzip), encrypt()]);
+        assert!(!encrypted.is_deterministic());
+        assert_ne!(
+            encrypted.apply(READING.to_vec()).unwrap(),
+            encrypted.apply(READING.to_vec()).unwrap()
+        );
+        assert!(pipeline(vec![Box::new(Compression::Zstd)]).is_deterministic());
+    }
+
+    #[test]
+    fn parse_skips_none_and_rejects_unknown_names() {
+        assert_eq!(
+            Pipeline::parse("gzip, none,zstd").unwrap().names(),
+            ["gzip", "zstd"]
+        );
+        assert!(Pipeline::parse("gzip,lz4").is_err());
+    }
+}
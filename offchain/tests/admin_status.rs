@@ -0,0 +1,108 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::AUTHORIZATION, Request, StatusCode},
+    Router,
+};
+use common::{admin_bearer, app_state, config, MockIpfs};
+use offchain::ipfs::{ipfs_router, IpfsConfig};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+fn app(config: IpfsConfig) -> Router {
+    ipfs_router(app_state(config))
+}
+
+async fn status(app: Router) -> (StatusCode, Value) {
+    status_at(app, "/api/v1/admin/status").await
+}
+
+async fn status_at(app: Router, uri: &str) -> (StatusCode, Value) {
+    let request = Request::get(uri)
+        .header(AUTHORIZATION, admin_bearer())
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn status_reports_a_reachable_node_and_idle_uploads() {
+    let (_mock, url) = MockIpfs::start().await;
+    let mut config = config(&url);
+    config.degrade_queue_depth = Some(8);
+
+    let (code, body) = status(app(config)).await;
+
+    assert_eq!(code, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({
+            "service_version": env!("CARGO_PKG_VERSION"),
+            "ipfs": {
+                "reachable": true,
+                "version": "0.29.0",
+                "last_error_at": null,
+                "last_error": null,
+            },
+            "uploads": {
+                "in_flight": 0,
+                "queued": 0,
+                "limit": 4,
+                "degrade_queue_depth": 8,
+                "degraded": false,
+            },
+            "dnslink_cache": { "hits": 0, "misses": 0, "hit_ratio": null },
+        })
+    );
+}
+
+#[tokio::test]
+async fn status_reports_an_unreachable_node() {
+    // a port nothing listens on any more
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let (code, body) = status(app(config(&url))).await;
+
+    // the status page itself still answers
+    assert_eq!(code, StatusCode::OK);
+    assert_eq!(body["ipfs"]["reachable"], false);
+    assert_eq!(body["ipfs"]["version"], Value::Null);
+    assert!(body["ipfs"]["last_error_at"].as_u64().is_some());
+    assert!(body["ipfs"]["last_error"]
+        .as_str()
+        .unwrap()
+        .starts_with("Failed to send request to IPFS API"));
+}
+
+#[tokio::test]
+async fn status_is_admin_only() {
+    let (mock, url) = MockIpfs::start().await;
+    let request = Request::get("/api/v1/admin/status")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app(config(&url)).oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(mock.requests("version").is_empty());
+}
+
+#[tokio::test]
+async fn status_can_be_projected_to_the_requested_fields() {
+    let (_mock, url) = MockIpfs::start().await;
+
+    let uri = "/api/v1/admin/status?fields=ipfs.reachable,uploads.limit";
+    let (code, body) = status_at(app(config(&url)), uri).await;
+
+    assert_eq!(code, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({ "ipfs": { "reachable": true }, "uploads": { "limit": 4 } })
+    );
+}
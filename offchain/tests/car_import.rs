@@ -0,0 +1,132 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::AUTHORIZATION, Request, StatusCode},
+    response::Response,
+};
+use ciborium::Value;
+use common::{admin_bearer, app_state, config, MockIpfs};
+use offchain::{
+    cid::{format_binary, push_varint},
+    ipfs::ipfs_router,
+};
+use sha2::{Digest, Sha256};
+use tower::ServiceExt;
+
+// CIDv1, raw codec, sha2-256
+fn raw_cid(data: &[u8]) -> Vec<u8> {
+    let mut cid = vec![0x01, 0x55, 0x12, 0x20];
+    cid.extend_from_slice(&Sha256::digest(data));
+    cid
+}
+
+// a CARv1 archive rooted at the first block; `tampered` blocks are stored
+// under the CID of different data
+fn archive(blocks: &[&[u8]], tampered: &[&[u8]]) -> Vec<u8> {
+    let mut link = vec![0x00];
+    link.extend(raw_cid(blocks[0]));
+    let header = Value::Map(vec![
+        (Value::Text("version".into()), Value::Integer(1.into())),
+        (
+            Value::Text("roots".into()),
+            Value::Array(vec![Value::Tag(42, Box::new(Value::Bytes(link)))]),
+        ),
+    ]);
+    let mut encoded = Vec::new();
+    ciborium::into_writer(&header, &mut encoded).unwrap();
+
+    let mut out = Vec::new();
+    push_varint(&mut out, encoded.len() as u64);
+    out.extend(encoded);
+
+    let sections = blocks
+        .iter()
+        .map(|data| (raw_cid(data), data.to_vec()))
+        .chain(
+            tampered
+                .iter()
+                .map(|data| (raw_cid(data), [*data, b"!"].concat())),
+        );
+    for (cid, data) in sections {
+        push_varint(&mut out, (cid.len() + data.len()) as u64);
+        out.extend(cid);
+        out.extend(data);
+    }
+    out
+}
+
+async fn import(url: &str, car: Vec<u8>) -> (StatusCode, serde_json::Value) {
+    let app = ipfs_router(app_state(config(url)));
+    let request = Request::post("/api/v1/admin/import.car")
+        .header(AUTHORIZATION, admin_bearer())
+        .body(Body::from(car))
+        .unwrap();
+    let response: Response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn imports_verified_blocks_and_pins_the_root() {
+    let (mock, url) = MockIpfs::start().await;
+    let root = format_binary(&raw_cid(b"hello"));
+
+    let (status, report) = import(&url, archive(&[b"hello", b"world"], &[])).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["imported"], 2);
+    assert_eq!(report["failed"], 0);
+    assert_eq!(report["roots"][0]["cid"], root);
+    assert_eq!(report["roots"][0]["pinned"], true);
+    let imports = mock.requests("dag/import");
+    assert_eq!(imports.len(), 1);
+    // the imported count is the node's own, from the import stats
+    assert_eq!(imports[0].query["stats"], "true");
+    assert_eq!(mock.pin_of(&root), Some(true));
+}
+
+#[tokio::test]
+async fn blocks_that_do_not_match_their_cid_are_left_out() {
+    let (mock, url) = MockIpfs::start().await;
+
+    let (status, report) = import(&url, archive(&[b"hello"], &[b"world"])).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["failed"], 1);
+    assert_eq!(
+        report["failures"][0]["cid"],
+        format_binary(&raw_cid(b"world"))
+    );
+    assert_eq!(mock.requests("dag/import").len(), 1);
+}
+
+#[tokio::test]
+async fn truncated_archives_are_rejected_before_reaching_the_node() {
+    let (mock, url) = MockIpfs::start().await;
+    let car = archive(&[b"hello", b"world"], &[]);
+
+    let (status, _) = import(&url, car[..car.len() - 3].to_vec()).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(mock.requests("dag/import").is_empty());
+}
+
+#[tokio::test]
+async fn huge_digest_lengths_are_rejected_without_panicking() {
+    let (mock, url) = MockIpfs::start().await;
+    let mut car = archive(&[b"hello"], &[]);
+    // a section whose CID claims a u64::MAX-byte digest
+    let mut cid = vec![0x01, 0x55, 0x12];
+    push_varint(&mut cid, u64::MAX);
+    push_varint(&mut car, cid.len() as u64 + 4);
+    car.extend(cid);
+    car.extend_from_slice(b"data");
+
+    let (status, _) = import(&url, car).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(mock.requests("dag/import").is_empty());
+}
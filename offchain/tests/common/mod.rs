@@ -0,0 +1,471 @@
+// An in-process stand-in for the Kubo HTTP API, enough of it for the
+// handlers under test
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use axum::{
+    extract::{Multipart, Query, Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use offchain::{
+    auth::AdminToken,
+    car::CarFile,
+    cid::MAX_SINGLE_BLOCK_BYTES,
+    ipfs::{AppState, AuthKind, IpfsClient, IpfsConfig, PinStrategy},
+    transform::Pipeline,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+// the one remote pinning service the mock node knows
+pub const PIN_SERVICE: &str = "mock-pinning";
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    // e.g. `pin/add`
+    pub endpoint: String,
+    pub query: HashMap<String, String>,
+    pub authorization: Option<String>,
+}
+
+#[derive(Default)]
+pub struct MockIpfs {
+    requests: Mutex<Vec<RecordedRequest>>,
+    blocks: Mutex<HashMap<String, Vec<u8>>>,
+    // DNSLink domain -> CID it publishes
+    dnslinks: Mutex<HashMap<String, String>>,
+    // DAG nodes as `dag/get` returns them, linking with `{"/": cid}`
+    dag: Mutex<HashMap<String, Value>>,
+    // CID -> recursive
+    pins: Mutex<HashMap<String, bool>>,
+    // CID -> remote pinning service
+    remote_pins: Mutex<HashMap<String, String>>,
+    add_delay: Mutex<Duration>,
+    adds_in_flight: AtomicUsize,
+    // most `add` calls ever running at once
+    peak_adds: AtomicUsize,
+    failing: AtomicBool,
+    // endpoints answering 500 while every other call succeeds
+    failing_endpoints: Mutex<Vec<String>>,
+}
+
+impl MockIpfs {
+    // serves the mock on a random local port, returning it with its base URL
+    pub async fn start() -> (Arc<Self>, String) {
+        let mock = Arc::new(Self::default());
+        let app = Router::new()
+            .route("/api/v0/add", post(add))
+            .route("/api/v0/pin/add", post(pin_add))
+            .route("/api/v0/pin/ls", post(pin_ls))
+            .route("/api/v0/pin/remote/add", post(pin_remote_add))
+            .route("/api/v0/cat", post(cat))
+            .route("/api/v0/refs", post(refs))
+            .route("/api/v0/dag/get", post(dag_get))
+            .route("/api/v0/name/resolve", post(name_resolve))
+            .route("/api/v0/files/stat", post(files_stat))
+            .route("/api/v0/dag/import", post(dag_import))
+            .route("/api/v0/version", post(version))
+            .layer(middleware::from_fn_with_state(mock.clone(), record))
+            .with_state(mock.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (mock, url)
+    }
+
+    pub fn requests(&self, endpoint: &str) -> Vec<RecordedRequest> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.endpoint == endpoint)
+            .cloned()
+            .collect()
+    }
+
+    pub fn store(&self, cid: &str, bytes: Vec<u8>) {
+        self.blocks.lock().unwrap().insert(cid.to_string(), bytes);
+    }
+
+    pub fn block(&self, cid: &str) -> Option<Vec<u8>> {
+        self.blocks.lock().unwrap().get(cid).cloned()
+    }
+
+    pub fn put_dag(&self, cid: &str, node: Value) {
+        self.dag.lock().unwrap().insert(cid.to_string(), node);
+    }
+
+    pub fn set_dnslink(&self, domain: &str, cid: &str) {
+        self.dnslinks
+            .lock()
+            .unwrap()
+            .insert(domain.to_string(), cid.to_string());
+    }
+
+    pub fn pin_of(&self, cid: &str) -> Option<bool> {
+        self.pins.lock().unwrap().get(cid).copied()
+    }
+
+    pub fn remote_pin_of(&self, cid: &str) -> Option<String> {
+        self.remote_pins.lock().unwrap().get(cid).cloned()
+    }
+
+    // every `add` waits this long before answering
+    pub fn delay_adds(&self, delay: Duration) {
+        *self.add_delay.lock().unwrap() = delay;
+    }
+
+    pub fn peak_adds(&self) -> usize {
+        self.peak_adds.load(Ordering::SeqCst)
+    }
+
+    // every call answers 500 until turned off again
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    // calls to `endpoint` alone answer 500
+    pub fn fail_endpoint(&self, endpoint: &str) {
+        self.failing_endpoints
+            .lock()
+            .unwrap()
+            .push(endpoint.to_string());
+    }
+}
+
+pub fn config(api_url: &str) -> IpfsConfig {
+    IpfsConfig {
+        api_url: api_url.to_string(),
+        api_path_prefix: String::new(),
+        pin_on_upload: true,
+        pipeline: Pipeline::default(),
+        upload_concurrency: 4,
+        degrade_queue_depth: None,
+        dnslink_ttl: Duration::from_secs(60),
+        remote_pin_service: None,
+        pin_strategy: PinStrategy::Local,
+        max_pinned_bytes: 1024 * 1024,
+        cid_version: 0,
+        raw_leaves: false,
+        auth: AuthKind::None,
+    }
+}
+
+pub fn app_state(config: IpfsConfig) -> AppState {
+    let mut state = AppState::with_ipfs_client(IpfsClient::new(config));
+    state.admin_token = AdminToken::new(ADMIN_TOKEN);
+    state
+}
+
+pub fn admin_bearer() -> String {
+    format!("Bearer {}", ADMIN_TOKEN)
+}
+
+async fn record(State(mock): State<Arc<MockIpfs>>, request: Request, next: Next) -> Response {
+    let endpoint = request
+        .uri()
+        .path()
+        .trim_start_matches("/api/v0/")
+        .to_string();
+    let query = Query::<HashMap<String, String>>::try_from_uri(request.uri())
+        .map(|Query(query)| query)
+        .unwrap_or_default();
+    let authorization = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let failing = mock.failing.load(Ordering::SeqCst)
+        || mock.failing_endpoints.lock().unwrap().contains(&endpoint);
+    mock.requests.lock().unwrap().push(RecordedRequest {
+        endpoint,
+        query,
+        authorization,
+    });
+
+    if failing {
+        return kubo_error("mock node is failing");
+    }
+    next.run(request).await
+}
+
+fn kubo_error(message: &str) -> Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "Message": message, "Code": 0, "Type": "error" })),
+    )
+        .into_response()
+}
+
+async fn add(
+    State(mock): State<Arc<MockIpfs>>,
+    Query(query): Query<HashMap<String, String>>,
+    mut multipart: Multipart,
+) -> Response {
+    let running = mock.adds_in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+    mock.peak_adds.fetch_max(running, Ordering::SeqCst);
+    let delay = *mock.add_delay.lock().unwrap();
+    tokio::time::sleep(delay).await;
+    mock.adds_in_flight.fetch_sub(1, Ordering::SeqCst);
+
+    let cid_version = query
+        .get("cid-version")
+        .and_then(|version| version.parse().ok())
+        .unwrap_or(0);
+    let raw_leaves = query.get("raw-leaves").is_some_and(|raw| raw == "true");
+    let only_hash = query.get("only-hash").is_some_and(|only| only == "true");
+
+    let mut lines = Vec::new();
+    while let Some(field) = multipart.next_field().await.unwrap() {
+        let name = field.file_name().unwrap_or_default().to_string();
+        let bytes = field.bytes().await.unwrap().to_vec();
+        let cid = mock_cid(&bytes, cid_version, raw_leaves);
+        lines.push(
+            json!({ "Name": name, "Hash": cid, "Size": bytes.len().to_string() }).to_string(),
+        );
+        if !only_hash {
+            mock.store(&cid, bytes);
+        }
+    }
+    if query
+        .get("wrap-with-directory")
+        .is_some_and(|wrap| wrap == "true")
+    {
+        // stands in for the UnixFS directory linking the files
+        let root = mock_cid(lines.join("\n").as_bytes(), cid_version, raw_leaves);
+        let links: Vec<Value> = lines
+            .iter()
+            .map(|line| {
+                let entry: Value = serde_json::from_str(line).unwrap();
+                json!({ "Name": entry["Name"], "Hash": { "/": entry["Hash"] } })
+            })
+            .collect();
+        if !only_hash {
+            mock.put_dag(&root, json!({ "Links": links }));
+        }
+        lines.push(json!({ "Name": "", "Hash": root, "Size": "0" }).to_string());
+    }
+    lines.join("\n").into_response()
+}
+
+// the real CID for single-block content; a multi-block DAG would need the
+// chunker, so larger content gets a stand-in derived from its hash
+fn mock_cid(bytes: &[u8], cid_version: u8, raw_leaves: bool) -> String {
+    if bytes.len() <= MAX_SINGLE_BLOCK_BYTES {
+        offchain::cid::compute(bytes, cid_version, raw_leaves).unwrap()
+    } else {
+        offchain::cid::compute(&Sha256::digest(bytes), cid_version, raw_leaves).unwrap()
+    }
+}
+
+async fn pin_add(
+    State(mock): State<Arc<MockIpfs>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let cid = query["arg"].clone();
+    let recursive = query.get("recursive").is_none_or(|r| r == "true");
+    mock.pins.lock().unwrap().insert(cid.clone(), recursive);
+    Json(json!({ "Pins": [cid] })).into_response()
+}
+
+async fn pin_ls(
+    State(mock): State<Arc<MockIpfs>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let cid = &query["arg"];
+    match mock.pin_of(cid) {
+        Some(recursive) => {
+            let kind = if recursive { "recursive" } else { "direct" };
+            Json(json!({ "Keys": { cid.as_str(): { "Type": kind } } })).into_response()
+        }
+        None => kubo_error(&format!("path '{}' is not pinned", cid)),
+    }
+}
+
+async fn pin_remote_add(
+    State(mock): State<Arc<MockIpfs>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let (cid, service) = (&query["arg"], &query["service"]);
+    if service != PIN_SERVICE {
+        return kubo_error(&format!("service not known: {}", service));
+    }
+    mock.remote_pins
+        .lock()
+        .unwrap()
+        .insert(cid.clone(), service.clone());
+    Json(json!({ "Cid": cid, "Name": "", "Status": "queued" })).into_response()
+}
+
+async fn cat(
+    State(mock): State<Arc<MockIpfs>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let Some(bytes) = mock.blocks.lock().unwrap().get(&query["arg"]).cloned() else {
+        return kubo_error("block was not found locally (offline)");
+    };
+    let number = |name: &str| {
+        query
+            .get(name)
+            .and_then(|value| value.parse::<usize>().ok())
+    };
+    let start = number("offset").unwrap_or(0).min(bytes.len());
+    let end = number("length").map_or(bytes.len(), |length| (start + length).min(bytes.len()));
+    bytes[start..end].to_vec().into_response()
+}
+
+// Resolves `cid/a/b/0` like the node: map keys and list indexes, following
+// links into other DAG nodes along the way
+async fn dag_get(
+    State(mock): State<Arc<MockIpfs>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let dag = mock.dag.lock().unwrap();
+    let mut segments = query["arg"].split('/');
+    let cid = segments.next().unwrap_or_default();
+    let Some(mut node) = dag.get(cid).cloned() else {
+        return kubo_error(&format!("block {} was not found locally (offline)", cid));
+    };
+
+    for segment in segments {
+        let child = match &node {
+            Value::Object(map) => map.get(segment).cloned(),
+            Value::Array(items) => segment
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| items.get(i).cloned()),
+            _ => None,
+        };
+        let Some(child) = child else {
+            return kubo_error(&format!("no link named {:?} under {}", segment, cid));
+        };
+        node = match child.get("/").and_then(Value::as_str) {
+            Some(linked) if dag.contains_key(linked) => dag[linked].clone(),
+            _ => child,
+        };
+    }
+    Json(node).into_response()
+}
+
+async fn name_resolve(
+    State(mock): State<Arc<MockIpfs>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let domain = query["arg"].trim_start_matches("/ipns/");
+    match mock.dnslinks.lock().unwrap().get(domain) {
+        Some(cid) => Json(json!({ "Path": format!("/ipfs/{}", cid) })).into_response(),
+        None => kubo_error(&format!(
+            "could not resolve name: {} has no DNSLink record",
+            domain
+        )),
+    }
+}
+
+// one line per link, as `refs --unique` streams them; blocks without a DAG node are leaves
+async fn refs(
+    State(mock): State<Arc<MockIpfs>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let cid = &query["arg"];
+    let links = match mock.dag.lock().unwrap().get(cid) {
+        Some(node) => {
+            let mut links = Vec::new();
+            collect_links(node, &mut links);
+            links
+        }
+        None if mock.blocks.lock().unwrap().contains_key(cid) => Vec::new(),
+        None => {
+            let line = json!({ "Ref": "", "Err": format!("block {} was not found locally (offline)", cid) });
+            return line.to_string().into_response();
+        }
+    };
+    let mut lines: Vec<String> = Vec::new();
+    for link in links {
+        let line = json!({ "Ref": link, "Err": "" }).to_string();
+        if !lines.contains(&line) {
+            lines.push(line);
+        }
+    }
+    lines.join("\n").into_response()
+}
+
+fn collect_links(node: &Value, links: &mut Vec<String>) {
+    match node {
+        Value::Object(map) => match map.get("/") {
+            Some(Value::String(cid)) if map.len() == 1 => links.push(cid.clone()),
+            _ => map.values().for_each(|value| collect_links(value, links)),
+        },
+        Value::Array(items) => items.iter().for_each(|item| collect_links(item, links)),
+        _ => {}
+    }
+}
+
+async fn files_stat(
+    State(mock): State<Arc<MockIpfs>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let cid = query["arg"].trim_start_matches("/ipfs/").to_string();
+    match mock.blocks.lock().unwrap().get(&cid) {
+        Some(bytes) => Json(json!({
+            "Hash": cid,
+            "Size": bytes.len(),
+            // a single UnixFS block wraps the data in a few bytes of protobuf
+            "CumulativeSize": bytes.len() + 11,
+            "Type": "file",
+        }))
+        .into_response(),
+        None => kubo_error(&format!("{} not found", cid)),
+    }
+}
+
+async fn dag_import(
+    State(mock): State<Arc<MockIpfs>>,
+    Query(query): Query<HashMap<String, String>>,
+    mut multipart: Multipart,
+) -> Response {
+    let Some(field) = multipart.next_field().await.unwrap() else {
+        return kubo_error("no CAR given");
+    };
+    let car = match CarFile::parse(&field.bytes().await.unwrap()) {
+        Ok(car) => car,
+        Err(e) => return kubo_error(&e.to_string()),
+    };
+
+    for block in &car.blocks {
+        mock.store(&block.cid, block.data.clone());
+    }
+    let mut lines: Vec<String> = car
+        .roots
+        .iter()
+        .map(|root| {
+            mock.pins.lock().unwrap().insert(root.clone(), true);
+            json!({ "Root": { "Cid": { "/": root }, "PinErrorMsg": "" } }).to_string()
+        })
+        .collect();
+    if query.get("stats").map(String::as_str) == Some("true") {
+        let bytes: usize = car.blocks.iter().map(|block| block.data.len()).sum();
+        lines.push(
+            json!({ "Stats": { "BlockCount": car.blocks.len(), "BlockBytesCount": bytes } })
+                .to_string(),
+        );
+    }
+    lines.join("\n").into_response()
+}
+
+async fn version() -> Response {
+    Json(json!({ "Version": "0.29.0", "Commit": "mock" })).into_response()
+}
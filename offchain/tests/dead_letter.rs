@@ -0,0 +1,99 @@
+mod common;
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+};
+use common::{app_state, config, MockIpfs};
+use offchain::{
+    dead_letter::DeadLetterStore,
+    ipfs::{ipfs_router, IpfsClient},
+};
+use serde_json::json;
+use tower::ServiceExt;
+
+// a fresh file per test, so tests running in parallel don't share a store
+fn store_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "offchain-dead-letters-{}-{}.jsonl",
+        std::process::id(),
+        name
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[tokio::test]
+async fn persistent_ipfs_failure_lands_in_the_store_and_replays_later() {
+    let (mock, url) = MockIpfs::start().await;
+    mock.set_failing(true);
+    let path = store_path("persistent-failure");
+    let store = Arc::new(DeadLetterStore::new(&path));
+    let mut state = app_state(config(&url));
+    state.dead_letters = Some(store.clone());
+    let reading = json!({ "sensor": "wh-1", "temperature": 21.5 });
+
+    let request = Request::post("/api/ipfs/upload")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "data": reading }).to_string()))
+        .unwrap();
+    let response = ipfs_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+    let letters = store.load().await.unwrap();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].data, reading);
+    assert!(letters[0].pin);
+    assert!(letters[0].error.contains("mock node is failing"));
+    let cid = letters[0].cid.clone().expect("CID computed locally");
+
+    let report = store.replay(&state.ipfs_client).await.unwrap();
+    assert_eq!((report.failed, report.remaining), (1, 1));
+
+    mock.set_failing(false);
+    let report = store.replay(&state.ipfs_client).await.unwrap();
+    assert_eq!(report.replayed.len(), 1);
+    assert_eq!(report.replayed[0], cid);
+    assert_eq!(report.remaining, 0);
+    assert!(store.load().await.unwrap().is_empty());
+    assert_eq!(mock.pin_of(&cid), Some(false));
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn failures_recorded_during_a_replay_are_kept() {
+    let (mock, url) = MockIpfs::start().await;
+    let client = IpfsClient::new(config(&url));
+    let path = store_path("append-during-replay");
+    let store = Arc::new(DeadLetterStore::new(&path));
+    let error = anyhow::anyhow!("node unreachable");
+    store
+        .record(&client, json!({ "sensor": "wh-1" }), true, &error)
+        .await;
+
+    mock.delay_adds(Duration::from_millis(300));
+    let replay = tokio::spawn({
+        let store = store.clone();
+        let client = IpfsClient::new(config(&url));
+        async move { store.replay(&client).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // recording doesn't wait for the replay's uploads
+    store
+        .record(&client, json!({ "sensor": "wh-2" }), true, &error)
+        .await;
+    assert!(!replay.is_finished());
+
+    let report = replay.await.unwrap().unwrap();
+    assert_eq!(report.replayed.len(), 1);
+    assert_eq!(report.remaining, 1);
+    let letters = store.load().await.unwrap();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].data, json!({ "sensor": "wh-2" }));
+
+    let _ = std::fs::remove_file(path);
+}
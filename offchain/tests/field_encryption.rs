@@ -0,0 +1,121 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        Request, StatusCode,
+    },
+    Router,
+};
+use common::{admin_bearer, app_state, config, MockIpfs};
+use offchain::{
+    encryption::FieldEncryptor,
+    ipfs::{ipfs_router, IpfsConfig},
+    transform::{Encrypt, Pipeline},
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+const PII_KEY: [u8; 32] = [3; 32];
+
+fn app(config: IpfsConfig) -> Router {
+    let mut state = app_state(config);
+    state.field_encryptor = Some(Arc::new(FieldEncryptor::new(
+        &PII_KEY,
+        vec![
+            "registration_data.email".to_string(),
+            "registration_data.phone_number".to_string(),
+        ],
+    )));
+    ipfs_router(state)
+}
+
+fn registration() -> Value {
+    json!({
+        "farmer_id": "f-17",
+        "registration_data": {
+            "name": "Asha",
+            "email": "asha@example.com",
+            "phone_number": "+91 98765 43210",
+        },
+    })
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+async fn upload(app: &Router) -> String {
+    let request = Request::post("/api/v1/ipfs/upload")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "data": registration() }).to_string()))
+        .unwrap();
+    let (status, body) = send(app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    body["cid"].as_str().unwrap().to_string()
+}
+
+async fn decrypt(app: &Router, cid: &str, authorization: Option<String>) -> (StatusCode, Value) {
+    let mut request = Request::get(format!("/api/v1/admin/ipfs/decrypt/{}", cid));
+    if let Some(authorization) = authorization {
+        request = request.header(AUTHORIZATION, authorization);
+    }
+    send(app, request.body(Body::empty()).unwrap()).await
+}
+
+#[tokio::test]
+async fn configured_fields_are_stored_encrypted_and_decrypt_for_admins() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = app(config(&url));
+
+    let cid = upload(&app).await;
+
+    let stored: Value = serde_json::from_slice(&mock.block(&cid).unwrap()).unwrap();
+    assert_eq!(stored["registration_data"]["name"], "Asha");
+    assert_ne!(stored["registration_data"]["email"], "asha@example.com");
+    assert_ne!(
+        stored["registration_data"]["phone_number"],
+        "+91 98765 43210"
+    );
+    assert_eq!(
+        stored["_encryption"]["fields"],
+        json!(["registration_data.email", "registration_data.phone_number"])
+    );
+
+    let (status, decrypted) = decrypt(&app, &cid, Some(admin_bearer())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(decrypted, registration());
+}
+
+#[tokio::test]
+async fn decrypting_needs_the_admin_token() {
+    let (_mock, url) = MockIpfs::start().await;
+    let app = app(config(&url));
+    let cid = upload(&app).await;
+
+    let (status, _) = decrypt(&app, &cid, None).await;
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn fields_round_trip_under_the_whole_object_encryption_too() {
+    let (mock, url) = MockIpfs::start().await;
+    let mut config = config(&url);
+    config.pipeline = Pipeline::new(vec![Box::new(Encrypt::new(&[9; 32]))]);
+    let app = app(config);
+
+    let cid = upload(&app).await;
+
+    // the stored object is opaque, the PII key alone couldn't read it
+    assert!(serde_json::from_slice::<Value>(&mock.block(&cid).unwrap()).is_err());
+    let (status, decrypted) = decrypt(&app, &cid, Some(admin_bearer())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(decrypted, registration());
+}
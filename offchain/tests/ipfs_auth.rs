@@ -0,0 +1,45 @@
+mod common;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::{config, MockIpfs};
+use offchain::ipfs::{AuthKind, IpfsClient};
+
+// the Authorization header the node saw for a `version` call made with `auth`
+async fn header_sent(auth: AuthKind) -> Option<String> {
+    let (mock, url) = MockIpfs::start().await;
+    let mut config = config(&url);
+    config.auth = auth;
+
+    IpfsClient::new(config).version().await.unwrap();
+
+    mock.requests("version").pop().unwrap().authorization
+}
+
+#[tokio::test]
+async fn no_auth_sends_no_header() {
+    assert_eq!(header_sent(AuthKind::None).await, None);
+}
+
+#[tokio::test]
+async fn basic_auth_sends_the_project_credentials() {
+    let header = header_sent(AuthKind::Basic {
+        id: "project".to_string(),
+        secret: "s3cret".to_string(),
+    })
+    .await;
+
+    assert_eq!(
+        header,
+        Some(format!("Basic {}", STANDARD.encode("project:s3cret")))
+    );
+}
+
+#[tokio::test]
+async fn bearer_auth_sends_the_token() {
+    let header = header_sent(AuthKind::Bearer {
+        token: "tok-123".to_string(),
+    })
+    .await;
+
+    assert_eq!(header.as_deref(), Some("Bearer tok-123"));
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    Router,
+};
+use common::{app_state, config, MockIpfs};
+use offchain::ipfs::ipfs_router;
+use serde_json::Value;
+use tower::ServiceExt;
+
+const BOUNDARY: &str = "offchain-test-boundary";
+
+// a multipart body with one part per (file name, content)
+fn multipart(files: &[(&str, &str)]) -> Body {
+    let mut body = String::new();
+    for (name, content) in files {
+        body.push_str(&format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\r\n{}\r\n",
+            BOUNDARY, name, content
+        ));
+    }
+    body.push_str(&format!("--{}--\r\n", BOUNDARY));
+    Body::from(body)
+}
+
+async fn send(app: Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn the_wrapping_directory_is_returned_as_the_root() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = ipfs_router(app_state(config(&url)));
+
+    let request = Request::post("/api/v1/ipfs/add-dir")
+        .header(
+            CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .body(multipart(&[
+            ("label.json", r#"{"batch": 7}"#),
+            ("readme.txt", "harvest batch 7"),
+        ]))
+        .unwrap();
+    let (status, body) = send(app.clone(), request).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let root = body["root_cid"].as_str().unwrap();
+    let files = body["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0]["path"], "label.json");
+    assert_eq!(files[1]["path"], "readme.txt");
+    assert!(files.iter().all(|file| file["cid"] != root));
+
+    // the root is a real object on the node, linking both files, and pinned with them
+    assert_eq!(mock.pin_of(root), Some(true));
+    let request = Request::get(format!("/api/v1/ipfs/refs/{}", root))
+        .body(Body::empty())
+        .unwrap();
+    let (status, refs) = send(app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(refs["refs"][0], files[0]["cid"]);
+    assert_eq!(refs["refs"][1], files[1]["cid"]);
+
+    let add = &mock.requests("add")[0];
+    assert_eq!(add.query["wrap-with-directory"], "true");
+    assert_eq!(add.query["pin"], "false");
+}
+
+#[tokio::test]
+async fn requests_without_files_are_rejected() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = ipfs_router(app_state(config(&url)));
+
+    let request = Request::post("/api/v1/ipfs/add-dir")
+        .header(
+            CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .body(multipart(&[]))
+        .unwrap();
+    let (status, _) = send(app, request).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(mock.requests("add").is_empty());
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+};
+use common::{app_state, config, MockIpfs};
+use offchain::ipfs::{ipfs_router, AuthKind, IpfsConfig};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+const TOKEN: &str = "node-secret-token";
+
+fn with_auth(mut config: IpfsConfig) -> IpfsConfig {
+    config.auth = AuthKind::Bearer {
+        token: TOKEN.to_string(),
+    };
+    config
+}
+
+async fn get(config: IpfsConfig, uri: &str) -> (StatusCode, Value) {
+    let request = Request::get(uri).body(Body::empty()).unwrap();
+    let response = ipfs_router(app_state(config))
+        .oneshot(request)
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn failed_gets_say_which_call_and_cid_failed() {
+    let (mock, url) = MockIpfs::start().await;
+    mock.set_failing(true);
+    let cid = offchain::cid::compute(b"hello world", 0, false).unwrap();
+
+    let (status, body) = get(
+        with_auth(config(&url)),
+        &format!("/api/v1/ipfs/dag/{}", cid),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body["context"], json!({ "op": "dag/get", "cid": cid }));
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("mock node is failing"));
+    // the node was called with the token, but the client never sees it
+    assert_eq!(
+        mock.requests("dag/get")[0].authorization.as_deref(),
+        Some(format!("Bearer {}", TOKEN).as_str())
+    );
+    assert!(!body.to_string().contains(TOKEN));
+    assert!(!body.to_string().contains(&url));
+}
+
+#[tokio::test]
+async fn unreachable_nodes_do_not_leak_their_address() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+    let cid = offchain::cid::compute(b"hello world", 0, false).unwrap();
+
+    let (status, body) = get(
+        with_auth(config(&url)),
+        &format!("/api/v1/ipfs/cat/{}", cid),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body["context"], json!({ "op": "files/stat", "cid": cid }));
+    assert_eq!(body["error"], "Failed to send request to IPFS API");
+    assert!(!body.to_string().contains(TOKEN));
+    assert!(!body.to_string().contains(&url));
+}
@@ -0,0 +1,203 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        Request, StatusCode,
+    },
+    Router,
+};
+use common::{admin_bearer, app_state, config, MockIpfs, PIN_SERVICE};
+use offchain::ipfs::ipfs_router;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+fn pin_request(uri: &str, authorization: Option<&str>) -> Request<Body> {
+    let mut request = Request::post(uri);
+    if let Some(authorization) = authorization {
+        request = request.header(AUTHORIZATION, authorization);
+    }
+    request.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn pin_requires_admin() {
+    let (mock, url) = MockIpfs::start().await;
+    let cid = offchain::cid::compute(b"hello world", 0, false).unwrap();
+    mock.store(&cid, b"hello world".to_vec());
+    let app = ipfs_router(app_state(config(&url)));
+
+    let uri = format!("/api/v1/ipfs/pin/{}", cid);
+    let response = app.clone().oneshot(pin_request(&uri, None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app
+        .oneshot(pin_request(&uri, Some("Bearer wrong")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    assert!(mock.requests("pin/add").is_empty());
+}
+
+#[tokio::test]
+async fn pin_rejects_malformed_cids() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = ipfs_router(app_state(config(&url)));
+
+    let response = app
+        .oneshot(pin_request(
+            "/api/v1/ipfs/pin/not-a-cid",
+            Some(&admin_bearer()),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(mock.requests("pin/add").is_empty());
+}
+
+#[tokio::test]
+async fn small_objects_are_pinned_directly_by_default() {
+    let (mock, url) = MockIpfs::start().await;
+    let cid = offchain::cid::compute(b"hello world", 0, false).unwrap();
+    mock.store(&cid, b"hello world".to_vec());
+    let app = ipfs_router(app_state(config(&url)));
+
+    let response = app
+        .oneshot(pin_request(
+            &format!("/api/v1/ipfs/pin/{}", cid),
+            Some(&admin_bearer()),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body["pin_type"], "direct");
+
+    let pins = mock.requests("pin/add");
+    assert_eq!(pins.len(), 1);
+    assert_eq!(pins[0].query["arg"], cid);
+    assert_eq!(pins[0].query["recursive"], "false");
+}
+
+#[tokio::test]
+async fn pin_type_query_overrides_the_default() {
+    let (mock, url) = MockIpfs::start().await;
+    let cid = offchain::cid::compute(b"hello world", 0, false).unwrap();
+    mock.store(&cid, b"hello world".to_vec());
+    let app = ipfs_router(app_state(config(&url)));
+
+    let response = app
+        .oneshot(pin_request(
+            &format!("/api/v1/ipfs/pin/{}?pin_type=recursive", cid),
+            Some(&admin_bearer()),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(mock.requests("pin/add")[0].query["recursive"], "true");
+}
+
+#[tokio::test]
+async fn objects_over_the_pin_limit_are_refused() {
+    let (mock, url) = MockIpfs::start().await;
+    let bytes = vec![7u8; 4096];
+    let cid = offchain::cid::compute(&bytes, 0, false).unwrap();
+    mock.store(&cid, bytes);
+    let mut config = config(&url);
+    config.max_pinned_bytes = 1024;
+    let app = ipfs_router(app_state(config));
+
+    let response = app
+        .oneshot(pin_request(
+            &format!("/api/v1/ipfs/pin/{}", cid),
+            Some(&admin_bearer()),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(mock.requests("pin/add").is_empty());
+}
+
+#[tokio::test]
+async fn unknown_cids_are_not_found() {
+    let (mock, url) = MockIpfs::start().await;
+    let cid = offchain::cid::compute(b"never uploaded", 0, false).unwrap();
+    let app = ipfs_router(app_state(config(&url)));
+
+    let response = app
+        .oneshot(pin_request(
+            &format!("/api/v1/ipfs/pin/{}", cid),
+            Some(&admin_bearer()),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(mock.requests("pin/add").is_empty());
+}
+
+// `app` with IPFS_REMOTE_PIN_SERVICE set to the service the mock knows
+fn remote_pin_app(url: &str) -> Router {
+    let mut config = config(url);
+    config.remote_pin_service = Some(PIN_SERVICE.to_string());
+    ipfs_router(app_state(config))
+}
+
+async fn pin_remote(app: Router, body: Value) -> (StatusCode, Value) {
+    let request = Request::post("/api/v1/ipfs/pin-remote")
+        .header(AUTHORIZATION, admin_bearer())
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn remote_pins_go_to_the_configured_service() {
+    let (mock, url) = MockIpfs::start().await;
+    let cid = offchain::cid::compute(b"hello world", 0, false).unwrap();
+
+    let (status, body) = pin_remote(remote_pin_app(&url), json!({ "cid": cid })).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({ "cid": cid, "service": PIN_SERVICE, "status": "queued" })
+    );
+    let requests = mock.requests("pin/remote/add");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].query["background"], "true");
+    assert_eq!(mock.remote_pin_of(&cid).as_deref(), Some(PIN_SERVICE));
+}
+
+#[tokio::test]
+async fn services_the_node_does_not_know_are_unprocessable() {
+    let (mock, url) = MockIpfs::start().await;
+    let cid = offchain::cid::compute(b"hello world", 0, false).unwrap();
+
+    let (status, body) = pin_remote(
+        remote_pin_app(&url),
+        json!({ "cid": cid, "service": "elsewhere" }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["error"].as_str().unwrap().contains("elsewhere"));
+    assert!(mock.remote_pin_of(&cid).is_none());
+}
+
+#[tokio::test]
+async fn remote_pins_need_a_service() {
+    let (mock, url) = MockIpfs::start().await;
+    let cid = offchain::cid::compute(b"hello world", 0, false).unwrap();
+    let app = ipfs_router(app_state(config(&url)));
+
+    let (status, _) = pin_remote(app, json!({ "cid": cid })).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(mock.requests("pin/remote/add").is_empty());
+}
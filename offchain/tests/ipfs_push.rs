@@ -0,0 +1,97 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    Router,
+};
+use common::{app_state, config, MockIpfs};
+use offchain::{
+    ipfs::ipfs_router,
+    signature::{HmacSecret, SIGNATURE_HEADER},
+};
+use serde_json::Value;
+use tower::ServiceExt;
+
+const SECRET: &str = "partner-secret";
+const BODY: &str = r#"{"data": {"sensor": "wh-1", "temperature": 21.5}}"#;
+
+fn app(url: &str, secret: HmacSecret) -> Router {
+    let mut state = app_state(config(url));
+    state.push_secret = secret;
+    ipfs_router(state)
+}
+
+async fn push(app: Router, body: &str, signature: Option<String>) -> (StatusCode, Value) {
+    let mut request = Request::post("/api/v1/ipfs/push").header(CONTENT_TYPE, "application/json");
+    if let Some(signature) = signature {
+        request = request.header(SIGNATURE_HEADER, signature);
+    }
+    let response = app
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn correctly_signed_pushes_are_uploaded() {
+    let (mock, url) = MockIpfs::start().await;
+    let secret = HmacSecret::new(SECRET);
+    let signature = secret.sign(BODY.as_bytes()).unwrap();
+
+    let (status, body) = push(app(&url, secret), BODY, Some(signature)).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["pinned"], true);
+    assert_eq!(mock.requests("add").len(), 1);
+}
+
+#[tokio::test]
+async fn tampered_bodies_are_rejected_before_parsing() {
+    let (mock, url) = MockIpfs::start().await;
+    let secret = HmacSecret::new(SECRET);
+    let signature = secret.sign(BODY.as_bytes()).unwrap();
+    let tampered = BODY.replace("21.5", "12.5");
+
+    let (status, _) = push(app(&url, secret), &tampered, Some(signature)).await;
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(mock.requests("add").is_empty());
+}
+
+#[tokio::test]
+async fn missing_signatures_are_rejected() {
+    let (mock, url) = MockIpfs::start().await;
+
+    let (status, _) = push(app(&url, HmacSecret::new(SECRET)), BODY, None).await;
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(mock.requests("add").is_empty());
+}
+
+#[tokio::test]
+async fn signed_bodies_of_the_wrong_shape_are_unprocessable() {
+    let (mock, url) = MockIpfs::start().await;
+    let secret = HmacSecret::new(SECRET);
+    let body = r#"{"pin": true}"#;
+    let signature = secret.sign(body.as_bytes()).unwrap();
+
+    let (status, _) = push(app(&url, secret), body, Some(signature)).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(mock.requests("add").is_empty());
+}
+
+#[tokio::test]
+async fn pushes_are_refused_without_a_configured_secret() {
+    let (mock, url) = MockIpfs::start().await;
+    let signature = HmacSecret::new(SECRET).sign(BODY.as_bytes()).unwrap();
+
+    let (status, _) = push(app(&url, HmacSecret::default()), BODY, Some(signature)).await;
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(mock.requests("add").is_empty());
+}
@@ -0,0 +1,381 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{
+        header::{ACCEPT, ACCEPT_RANGES, CACHE_CONTROL, CONTENT_RANGE, CONTENT_TYPE, RANGE},
+        Request, StatusCode,
+    },
+    Router,
+};
+use common::{app_state, config, MockIpfs};
+use offchain::{ipfs::ipfs_router, negotiate::CBOR_CONTENT_TYPE};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tower::ServiceExt;
+
+// raw leaves holding `left` and `right`, and dag-cbor CIDs standing in for
+// the root node and the metadata node below it
+const ROOT: &str = "bafyreicicneu2e36cyy3xiyb2wwkw3t3w6vhjtqrqxkfmvs66uoxg5txwi";
+const META: &str = "bafyreihkhplt4k2qnyafe4rswpwxipagnwudvdrqm33cu4phl243jkq5wy";
+const LEFT: &str = "bafkreibwb6cagwkceq6gunstplrpqzzuqxtmarcvucufudnrs2ipevauqa";
+const DOMAIN: &str = "docs.example.org";
+const RIGHT: &str = "bafkreibhaqxu43wkpufsu7xeajw7f3h2khjthhtncivkbgirr3gyky523e";
+
+fn app(url: &str) -> Router {
+    ipfs_router(app_state(config(url)))
+}
+
+// a root linking to a metadata node and two leaves, the left one twice
+fn store_tree(mock: &MockIpfs) {
+    mock.put_dag(
+        ROOT,
+        json!({
+            "left": { "/": LEFT },
+            "right": { "/": RIGHT },
+            "again": [{ "/": LEFT }],
+            "meta": { "/": META },
+        }),
+    );
+    mock.put_dag(
+        META,
+        json!({ "farm": { "name": "Green Acres", "plots": [{ "crop": "rice" }] } }),
+    );
+    mock.store(LEFT, b"left".to_vec());
+    mock.store(RIGHT, b"right".to_vec());
+}
+
+async fn get(app: Router, uri: &str, accept: Option<&str>) -> axum::response::Response {
+    let mut request = Request::get(uri);
+    if let Some(accept) = accept {
+        request = request.header(ACCEPT, accept);
+    }
+    app.oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+// `cat` of a ten byte object, with an optional `Range` header
+async fn cat(range: Option<&str>) -> (std::sync::Arc<MockIpfs>, axum::response::Response) {
+    let (mock, url) = MockIpfs::start().await;
+    mock.store(LEFT, b"0123456789".to_vec());
+
+    let mut request = Request::get(format!("/api/v1/ipfs/cat/{}", LEFT));
+    if let Some(range) = range {
+        request = request.header(RANGE, range);
+    }
+    let response = app(&url)
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    (mock, response)
+}
+
+async fn text(response: axum::response::Response) -> String {
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+async fn json_body(response: axum::response::Response) -> Value {
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn refs_can_be_read_as_cbor() {
+    let (mock, url) = MockIpfs::start().await;
+    store_tree(&mock);
+
+    let response = get(
+        app(&url),
+        &format!("/api/v1/ipfs/refs/{}", ROOT),
+        Some(CBOR_CONTENT_TYPE),
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], CBOR_CONTENT_TYPE);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let decoded: Value = ciborium::from_reader(body.as_ref()).unwrap();
+    assert_eq!(decoded, json!({ "cid": ROOT, "refs": [LEFT, META, RIGHT] }));
+}
+
+#[tokio::test]
+async fn refs_lists_each_link_once() {
+    let (mock, url) = MockIpfs::start().await;
+    store_tree(&mock);
+
+    let response = get(app(&url), &format!("/api/v1/ipfs/refs/{}", ROOT), None).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        json_body(response).await,
+        json!({ "cid": ROOT, "refs": [LEFT, META, RIGHT] })
+    );
+    assert_eq!(mock.requests("refs")[0].query["unique"], "true");
+}
+
+#[tokio::test]
+async fn leaves_have_no_refs() {
+    let (mock, url) = MockIpfs::start().await;
+    store_tree(&mock);
+
+    let response = get(app(&url), &format!("/api/v1/ipfs/refs/{}", LEFT), None).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json_body(response).await["refs"], json!([]));
+}
+
+#[tokio::test]
+async fn refs_of_unknown_objects_are_not_found() {
+    let (_mock, url) = MockIpfs::start().await;
+
+    let response = get(app(&url), &format!("/api/v1/ipfs/refs/{}", ROOT), None).await;
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn dag_paths_are_followed_through_links_into_nested_nodes() {
+    let (mock, url) = MockIpfs::start().await;
+    store_tree(&mock);
+
+    let response = get(
+        app(&url),
+        &format!("/api/v1/ipfs/dag/{}/meta/farm/plots/0", ROOT),
+        None,
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json_body(response).await, json!({ "crop": "rice" }));
+    let requests = mock.requests("dag/get");
+    assert_eq!(
+        requests[0].query["arg"],
+        format!("{}/meta/farm/plots/0", ROOT)
+    );
+}
+
+#[tokio::test]
+async fn dag_nodes_are_served_whole_without_a_path() {
+    let (mock, url) = MockIpfs::start().await;
+    store_tree(&mock);
+
+    let response = get(app(&url), &format!("/api/v1/ipfs/dag/{}", META), None).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json_body(response).await["farm"]["name"], "Green Acres");
+}
+
+#[tokio::test]
+async fn missing_dag_paths_are_not_found() {
+    let (mock, url) = MockIpfs::start().await;
+    store_tree(&mock);
+
+    let response = get(
+        app(&url),
+        &format!("/api/v1/ipfs/dag/{}/meta/farm/owner", ROOT),
+        None,
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        json_body(response).await["error"],
+        format!("No DAG node at {}/meta/farm/owner", ROOT)
+    );
+}
+
+#[tokio::test]
+async fn dag_paths_cannot_climb_out_of_the_cid() {
+    let (mock, url) = MockIpfs::start().await;
+
+    let response = get(
+        app(&url),
+        &format!("/api/v1/ipfs/dag/{}/meta/../..", ROOT),
+        None,
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(mock.requests("dag/get").is_empty());
+}
+
+#[tokio::test]
+async fn dnslink_resolutions_are_cached_for_the_ttl() {
+    let (mock, url) = MockIpfs::start().await;
+    mock.set_dnslink(DOMAIN, ROOT);
+    let app = app(&url);
+
+    let response = get(
+        app.clone(),
+        &format!("/api/v1/ipfs/dnslink/{}", DOMAIN),
+        None,
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        json_body(response).await,
+        json!({ "domain": DOMAIN, "cid": ROOT })
+    );
+
+    let uri = format!("/api/v1/ipfs/dnslink/{}?cid={}", DOMAIN, LEFT);
+    let response = get(app, &uri, None).await;
+    assert_eq!(json_body(response).await["matches"], false);
+
+    let resolves = mock.requests("name/resolve");
+    assert_eq!(resolves.len(), 1);
+    assert_eq!(resolves[0].query["arg"], format!("/ipns/{}", DOMAIN));
+}
+
+#[tokio::test]
+async fn dnslink_is_asked_again_once_the_ttl_has_passed() {
+    let (mock, url) = MockIpfs::start().await;
+    mock.set_dnslink(DOMAIN, ROOT);
+    let mut config = config(&url);
+    config.dnslink_ttl = Duration::ZERO;
+    let app = ipfs_router(app_state(config));
+
+    for _ in 0..2 {
+        let uri = format!("/api/v1/ipfs/dnslink/{}?cid={}", DOMAIN, ROOT);
+        let response = get(app.clone(), &uri, None).await;
+        assert_eq!(json_body(response).await["matches"], true);
+    }
+    assert_eq!(mock.requests("name/resolve").len(), 2);
+}
+
+#[tokio::test]
+async fn domains_without_dnslink_are_not_found() {
+    let (_mock, url) = MockIpfs::start().await;
+
+    let response = get(app(&url), &format!("/api/v1/ipfs/dnslink/{}", DOMAIN), None).await;
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn ranges_are_served_as_partial_content() {
+    let (mock, response) = cat(Some("bytes=2-5")).await;
+
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 2-5/10");
+    assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+    assert_eq!(text(response).await, "2345");
+    let cat = &mock.requests("cat")[0];
+    assert_eq!(cat.query["offset"], "2");
+    assert_eq!(cat.query["length"], "4");
+}
+
+#[tokio::test]
+async fn suffix_ranges_serve_the_end_of_the_object() {
+    let (_mock, response) = cat(Some("bytes=-3")).await;
+
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 7-9/10");
+    assert_eq!(text(response).await, "789");
+}
+
+#[tokio::test]
+async fn ranges_past_the_end_are_not_satisfiable() {
+    let (mock, response) = cat(Some("bytes=20-")).await;
+
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes */10");
+    assert!(mock.requests("cat").is_empty());
+}
+
+#[tokio::test]
+async fn without_a_range_the_whole_object_is_served() {
+    let (_mock, response) = cat(None).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key(CONTENT_RANGE));
+    assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+    assert_eq!(text(response).await, "0123456789");
+}
+
+#[tokio::test]
+async fn reads_by_cid_are_immutable_and_dnslink_is_not() {
+    let (mock, url) = MockIpfs::start().await;
+    store_tree(&mock);
+    mock.set_dnslink(DOMAIN, ROOT);
+    let mut state = app_state(config(&url));
+    state.immutable_max_age = Duration::from_secs(600);
+    let app = ipfs_router(state);
+
+    for uri in [
+        format!("/api/v1/ipfs/dag/{}", ROOT),
+        format!("/api/v1/ipfs/dag/{}/meta/farm", ROOT),
+        format!("/api/v1/ipfs/refs/{}", ROOT),
+        format!("/api/v1/ipfs/cat/{}", LEFT),
+    ] {
+        let response = get(app.clone(), &uri, None).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        assert_eq!(
+            response.headers()[CACHE_CONTROL],
+            "public, max-age=600, immutable",
+            "{}",
+            uri
+        );
+    }
+
+    let response = get(app, &format!("/api/v1/ipfs/dnslink/{}", DOMAIN), None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CACHE_CONTROL], "no-cache");
+}
+
+#[tokio::test]
+async fn errors_are_not_marked_cacheable() {
+    let (_mock, url) = MockIpfs::start().await;
+
+    let response = get(app(&url), &format!("/api/v1/ipfs/dag/{}", ROOT), None).await;
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(!response.headers().contains_key(CACHE_CONTROL));
+}
+
+#[tokio::test]
+async fn refs_can_be_projected_to_the_requested_fields() {
+    let (mock, url) = MockIpfs::start().await;
+    store_tree(&mock);
+
+    let uri = format!("/api/v1/ipfs/refs/{}?fields=refs", ROOT);
+    let response = get(app(&url), &uri, None).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        json_body(response).await,
+        json!({ "refs": [LEFT, META, RIGHT] })
+    );
+}
+
+#[tokio::test]
+async fn dnslink_answers_can_be_projected_alongside_other_parameters() {
+    let (mock, url) = MockIpfs::start().await;
+    mock.set_dnslink(DOMAIN, ROOT);
+
+    let uri = format!(
+        "/api/v1/ipfs/dnslink/{}?cid={}&fields=matches",
+        DOMAIN, ROOT
+    );
+    let response = get(app(&url), &uri, None).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json_body(response).await, json!({ "matches": true }));
+}
+
+#[tokio::test]
+async fn unknown_fields_are_rejected_on_every_read_endpoint() {
+    let (mock, url) = MockIpfs::start().await;
+    store_tree(&mock);
+    mock.set_dnslink(DOMAIN, ROOT);
+
+    for uri in [
+        format!("/api/v1/ipfs/refs/{}?fields=nope", ROOT),
+        format!("/api/v1/ipfs/dag/{}?fields=nope", ROOT),
+        format!("/api/v1/ipfs/dnslink/{}?fields=nope", DOMAIN),
+    ] {
+        let response = get(app(&url), &uri, None).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
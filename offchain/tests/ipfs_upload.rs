@@ -0,0 +1,273 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    Router,
+};
+use common::{app_state, config, MockIpfs, PIN_SERVICE};
+use futures::future::join_all;
+use offchain::ipfs::ipfs_router;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tower::ServiceExt;
+
+async fn upload(app: &Router, data: Value) -> (StatusCode, Value) {
+    upload_request(app, json!({ "data": data })).await
+}
+
+// `request` is the whole upload body, for tests setting `pin` and friends
+async fn upload_request(app: &Router, request: Value) -> (StatusCode, Value) {
+    let request = Request::post("/api/v1/ipfs/upload")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(request.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn second_upload_of_the_same_object_is_deduplicated() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = ipfs_router(app_state(config(&url)));
+    let reading = json!({ "sensor": "wh-1", "temperature": 21.5 });
+
+    let (status, first) = upload(&app, reading.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first["deduplicated"], false);
+    assert_eq!(first["pinned"], true);
+
+    let (status, second) = upload(&app, reading).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(second["deduplicated"], true);
+    assert_eq!(second["cid"], first["cid"]);
+
+    // the duplicate check hashed locally, so the node only saw the one upload
+    let adds = mock.requests("add");
+    assert_eq!(adds.len(), 1);
+    assert_eq!(adds[0].query["only-hash"], "false");
+}
+
+#[tokio::test]
+async fn local_cids_follow_the_configured_cid_version() {
+    let (mock, url) = MockIpfs::start().await;
+    let mut config = config(&url);
+    config.cid_version = 1;
+    config.raw_leaves = true;
+    let app = ipfs_router(app_state(config));
+    let reading = json!({ "sensor": "wh-2" });
+
+    let (_, first) = upload(&app, reading.clone()).await;
+    assert!(first["cid"].as_str().unwrap().starts_with("bafkrei"));
+    let (_, second) = upload(&app, reading).await;
+    assert_eq!(second["deduplicated"], true);
+    assert_eq!(mock.requests("add").len(), 1);
+}
+
+#[tokio::test]
+async fn objects_larger_than_a_block_are_hashed_by_the_node() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = ipfs_router(app_state(config(&url)));
+    let large = json!({ "padding": "x".repeat(300 * 1024) });
+
+    let (status, first) = upload(&app, large).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first["deduplicated"], false);
+
+    let adds = mock.requests("add");
+    assert_eq!(adds.len(), 2);
+    assert_eq!(adds[0].query["only-hash"], "true");
+    assert_eq!(adds[1].query["only-hash"], "false");
+    assert_eq!(mock.requests("pin/add")[0].query["recursive"], "true");
+}
+
+#[tokio::test]
+async fn unpinned_uploads_are_never_reported_as_pinned() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = ipfs_router(app_state(config(&url)));
+    let request = json!({ "data": { "sensor": "wh-3" }, "pin": false });
+
+    for _ in 0..2 {
+        let (status, body) = upload_request(&app, request.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["pinned"], false);
+    }
+    assert!(mock.requests("pin/add").is_empty());
+}
+
+#[tokio::test]
+async fn duplicates_of_pinned_content_are_only_pinned_when_asked() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = ipfs_router(app_state(config(&url)));
+    let data = json!({ "sensor": "wh-4" });
+
+    let (_, first) = upload_request(&app, json!({ "data": data, "pin": true })).await;
+    assert_eq!(first["pinned"], true);
+
+    let (status, second) = upload_request(&app, json!({ "data": data, "pin": false })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(second["deduplicated"], true);
+    assert_eq!(second["pinned"], false);
+    assert_eq!(mock.requests("pin/add").len(), 1);
+}
+
+#[tokio::test]
+async fn objects_over_the_pin_limit_are_stored_but_not_pinned() {
+    let (mock, url) = MockIpfs::start().await;
+    let mut config = config(&url);
+    config.max_pinned_bytes = 16;
+    let app = ipfs_router(app_state(config));
+
+    let (status, body) = upload(&app, json!({ "padding": "x".repeat(64) })).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["cid"].is_string());
+    assert_eq!(body["pinned"], false);
+    assert!(body["warning"].as_str().unwrap().contains("pin limit"));
+    assert!(mock.requests("pin/add").is_empty());
+}
+
+#[tokio::test]
+async fn a_failed_size_check_skips_the_pin_instead_of_failing_the_upload() {
+    let (mock, url) = MockIpfs::start().await;
+    mock.fail_endpoint("files/stat");
+    let app = ipfs_router(app_state(config(&url)));
+
+    let (status, body) = upload(&app, json!({ "sensor": "wh-5" })).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["cid"].is_string());
+    assert_eq!(body["pinned"], false);
+    assert!(body["warning"].as_str().unwrap().contains("not pinned"));
+    assert!(mock.requests("pin/add").is_empty());
+}
+
+#[tokio::test]
+async fn the_pre_v1_path_still_uploads() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = ipfs_router(app_state(config(&url)));
+    let request = Request::post("/api/ipfs/upload")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({ "data": { "sensor": "wh-6" } }).to_string(),
+        ))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(mock.requests("add").len(), 1);
+}
+
+// uploads `data` with `pin_strategy`, against a node that knows `PIN_SERVICE`
+async fn upload_with_strategy(strategy: &str) -> (std::sync::Arc<MockIpfs>, Value) {
+    let (mock, url) = MockIpfs::start().await;
+    let mut config = config(&url);
+    config.remote_pin_service = Some(PIN_SERVICE.to_string());
+    let app = ipfs_router(app_state(config));
+
+    let request = json!({ "data": { "strategy": strategy }, "pin_strategy": strategy });
+    let (status, body) = upload_request(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    (mock, body)
+}
+
+#[tokio::test]
+async fn the_local_strategy_pins_on_the_node_only() {
+    let (mock, body) = upload_with_strategy("local").await;
+    let cid = body["cid"].as_str().unwrap();
+
+    assert_eq!(body["pinned"], true);
+    assert!(body.get("remote_pin").is_none());
+    assert!(mock.pin_of(cid).is_some());
+    assert!(mock.requests("pin/remote/add").is_empty());
+}
+
+#[tokio::test]
+async fn the_remote_strategy_pins_at_the_service_only() {
+    let (mock, body) = upload_with_strategy("remote").await;
+    let cid = body["cid"].as_str().unwrap();
+
+    assert_eq!(body["pinned"], false);
+    assert_eq!(body["remote_pin"]["service"], PIN_SERVICE);
+    assert_eq!(body["remote_pin"]["status"], "queued");
+    assert!(mock.requests("pin/add").is_empty());
+    assert_eq!(mock.remote_pin_of(cid).as_deref(), Some(PIN_SERVICE));
+}
+
+#[tokio::test]
+async fn the_both_strategy_pins_on_the_node_and_at_the_service() {
+    let (mock, body) = upload_with_strategy("both").await;
+    let cid = body["cid"].as_str().unwrap();
+
+    assert_eq!(body["pinned"], true);
+    assert_eq!(body["remote_pin"]["status"], "queued");
+    assert!(mock.pin_of(cid).is_some());
+    assert_eq!(mock.remote_pin_of(cid).as_deref(), Some(PIN_SERVICE));
+}
+
+#[tokio::test]
+async fn concurrent_identical_uploads_reach_the_node_once() {
+    let (mock, url) = MockIpfs::start().await;
+    // slow enough that every upload starts before the first one finishes
+    mock.delay_adds(Duration::from_millis(200));
+    let app = ipfs_router(app_state(config(&url)));
+    let reading = json!({ "sensor": "wh-7", "temperature": 19.0 });
+
+    let results = join_all((0..8).map(|_| upload(&app, reading.clone()))).await;
+
+    assert!(results.iter().all(|(status, _)| *status == StatusCode::OK));
+    assert!(results
+        .iter()
+        .all(|(_, body)| body["cid"] == results[0].1["cid"]));
+    assert_eq!(mock.requests("add").len(), 1);
+}
+
+#[tokio::test]
+async fn adds_never_exceed_the_upload_concurrency() {
+    let (mock, url) = MockIpfs::start().await;
+    mock.delay_adds(Duration::from_millis(100));
+    let mut config = config(&url);
+    config.upload_concurrency = 2;
+    let app = ipfs_router(app_state(config));
+
+    let uploads = (0..8).map(|i| upload(&app, json!({ "sensor": format!("wh-{}", i) })));
+    let results = join_all(uploads).await;
+
+    assert!(results.iter().all(|(status, _)| *status == StatusCode::OK));
+    assert_eq!(mock.requests("add").len(), 8);
+    assert_eq!(mock.peak_adds(), 2);
+}
+
+#[tokio::test]
+async fn an_explicit_pin_overrides_the_pin_on_upload_default() {
+    for (pin_on_upload, pin, expected) in [
+        (false, None, false),
+        (false, Some(true), true),
+        (true, None, true),
+        (true, Some(false), false),
+    ] {
+        let (mock, url) = MockIpfs::start().await;
+        let mut config = config(&url);
+        config.pin_on_upload = pin_on_upload;
+        let app = ipfs_router(app_state(config));
+
+        let mut request = json!({ "data": { "sensor": "wh-8" } });
+        if let Some(pin) = pin {
+            request["pin"] = json!(pin);
+        }
+        let (status, body) = upload_request(&app, request).await;
+
+        let case = format!("pin_on_upload={} pin={:?}", pin_on_upload, pin);
+        assert_eq!(status, StatusCode::OK, "{}", case);
+        assert_eq!(body["pinned"], expected, "{}", case);
+        assert_eq!(
+            mock.requests("pin/add").len(),
+            expected as usize,
+            "{}",
+            case
+        );
+    }
+}
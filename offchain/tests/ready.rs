@@ -0,0 +1,70 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    Router,
+};
+use common::{app_state, config, MockIpfs};
+use offchain::ipfs::{ipfs_router, ready_router};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+async fn ready(app: &Router) -> (StatusCode, Value) {
+    let request = Request::get("/ready").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn not_ready_without_an_ipfs_client() {
+    let (status, body) = ready(&ready_router(None)).await;
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "unavailable");
+}
+
+#[tokio::test]
+async fn degraded_while_the_upload_queue_is_over_the_threshold() {
+    let (mock, url) = MockIpfs::start().await;
+    mock.delay_adds(Duration::from_millis(300));
+    let mut config = config(&url);
+    config.upload_concurrency = 1;
+    config.degrade_queue_depth = Some(1);
+    let state = app_state(config);
+    let client = Arc::clone(&state.ipfs_client);
+    let uploads = ipfs_router(state);
+    let probe = ready_router(Some(client.clone()));
+
+    assert_eq!(ready(&probe).await.0, StatusCode::OK);
+
+    // one upload holds the only slot while the other two queue behind it
+    let pending: Vec<_> = (0..3)
+        .map(|i| {
+            let request = Request::post("/api/v1/ipfs/upload")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(json!({ "data": { "n": i } }).to_string()))
+                .unwrap();
+            tokio::spawn(uploads.clone().oneshot(request))
+        })
+        .collect();
+    while client.upload_queue_depth() < 2 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let (status, body) = ready(&probe).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "degraded");
+    assert_eq!(body["queued"], 2);
+
+    for upload in pending {
+        assert_eq!(upload.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+    let (status, body) = ready(&probe).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["queued"], 0);
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    Router,
+};
+use common::{app_state, config, MockIpfs};
+use offchain::ipfs::ipfs_router;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+// a CIDv1 the mock node has never seen
+const UNKNOWN_SCHEMA: &str = "bafkreibwb6cagwkceq6gunstplrpqzzuqxtmarcvucufudnrs2ipevauqa";
+
+fn reading_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["sensor", "temperature"],
+        "properties": {
+            "sensor": { "type": "string" },
+            "temperature": { "type": "number" },
+        },
+    })
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+fn post(uri: &str, body: Value) -> Request<Body> {
+    Request::post(uri)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+async fn register(app: &Router, schema: Value) -> (StatusCode, Value) {
+    send(app, post("/api/v1/schemas", schema)).await
+}
+
+async fn upload(app: &Router, data: Value, schema_id: &str) -> (StatusCode, Value) {
+    let body = json!({ "data": data, "schema_id": schema_id });
+    send(app, post("/api/v1/ipfs/upload", body)).await
+}
+
+#[tokio::test]
+async fn registered_schemas_are_stored_and_readable() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = ipfs_router(app_state(config(&url)));
+
+    let (status, body) = register(&app, reading_schema()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let schema_id = body["schema_id"].as_str().unwrap();
+    assert!(mock.pin_of(schema_id).is_some());
+
+    let request = Request::get(format!("/api/v1/schemas/{}", schema_id))
+        .body(Body::empty())
+        .unwrap();
+    let (status, document) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(document, reading_schema());
+}
+
+#[tokio::test]
+async fn documents_that_are_not_schemas_are_rejected() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = ipfs_router(app_state(config(&url)));
+
+    let (status, _) = register(&app, json!({ "type": 12 })).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(mock.requests("add").is_empty());
+}
+
+#[tokio::test]
+async fn uploads_are_validated_against_their_schema() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = ipfs_router(app_state(config(&url)));
+    let (_, body) = register(&app, reading_schema()).await;
+    let schema_id = body["schema_id"].as_str().unwrap();
+
+    let valid = json!({ "sensor": "wh-1", "temperature": 21.5 });
+    let (status, _) = upload(&app, valid, schema_id).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let invalid = json!({ "sensor": "wh-1", "temperature": "warm" });
+    let (status, body) = upload(&app, invalid, schema_id).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["error"].as_str().unwrap().contains("/temperature"));
+
+    // the schema and the valid reading
+    assert_eq!(mock.requests("add").len(), 2);
+}
+
+#[tokio::test]
+async fn uploads_naming_an_unknown_schema_are_not_found() {
+    let (mock, url) = MockIpfs::start().await;
+    let app = ipfs_router(app_state(config(&url)));
+
+    let (status, _) = upload(&app, json!({ "sensor": "wh-1" }), UNKNOWN_SCHEMA).await;
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(mock.requests("add").is_empty());
+}
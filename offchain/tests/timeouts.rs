@@ -0,0 +1,89 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    middleware::from_fn_with_state,
+    Router,
+};
+use common::{app_state, config, MockIpfs};
+use offchain::{config::HandlerTimeouts, ipfs::ipfs_router, middleware::request_timeout};
+use tower::ServiceExt;
+
+const BUDGET: Duration = Duration::from_millis(200);
+const NODE_DELAY: Duration = Duration::from_millis(600);
+
+fn app(url: &str, timeouts: HandlerTimeouts) -> Router {
+    ipfs_router(app_state(config(url))).layer(from_fn_with_state(timeouts, request_timeout))
+}
+
+fn upload_request() -> Request<Body> {
+    Request::post("/api/ipfs/upload")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"data": {"sensor": "wh-1"}}"#))
+        .unwrap()
+}
+
+fn add_dir_request() -> Request<Body> {
+    let boundary = "timeout-test-boundary";
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"readings/a.json\"\r\n\
+         Content-Type: application/json\r\n\r\n{{\"a\":1}}\r\n--{b}--\r\n",
+        b = boundary
+    );
+    Request::post("/api/v1/ipfs/add-dir")
+        .header(
+            CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", boundary),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn slow_node_answers_504_within_the_budget() {
+    let (mock, url) = MockIpfs::start().await;
+    mock.delay_adds(NODE_DELAY);
+
+    let started = Instant::now();
+    let response = app(&url, HandlerTimeouts::new(Some(BUDGET)))
+        .oneshot(upload_request())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert!(started.elapsed() < NODE_DELAY);
+}
+
+#[tokio::test]
+async fn bulk_routes_have_no_budget_by_default() {
+    let (mock, url) = MockIpfs::start().await;
+    mock.delay_adds(NODE_DELAY);
+
+    let response = app(&url, HandlerTimeouts::new(Some(BUDGET)))
+        .oneshot(add_dir_request())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn routes_can_get_their_own_budget() {
+    let (mock, url) = MockIpfs::start().await;
+    mock.delay_adds(NODE_DELAY);
+
+    let extended = HandlerTimeouts::new(Some(BUDGET))
+        .with_routes("/api/ipfs/upload=5000")
+        .unwrap();
+    let response = app(&url, extended).oneshot(upload_request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let limited = HandlerTimeouts::new(None)
+        .with_routes("/api/v1/ipfs/add-dir=200")
+        .unwrap();
+    let response = app(&url, limited).oneshot(add_dir_request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+}
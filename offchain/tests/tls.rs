@@ -0,0 +1,93 @@
+use std::{
+    net::{SocketAddr, TcpListener},
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use rcgen::{generate_simple_self_signed, CertifiedKey};
+use reqwest::{Certificate, StatusCode};
+
+const STARTUP: Duration = Duration::from_secs(20);
+
+// the server binary, killed when the test ends whether it passed or not
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn free_addr() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+// a self-signed certificate for `localhost`, written out as TLS_CERT/TLS_KEY PEM files
+fn write_certificate(dir: &PathBuf) -> (String, PathBuf, PathBuf) {
+    let CertifiedKey { cert, key_pair } =
+        generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    std::fs::create_dir_all(dir).unwrap();
+    let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+    std::fs::write(&cert_path, cert.pem()).unwrap();
+    std::fs::write(&key_path, key_pair.serialize_pem()).unwrap();
+    (cert.pem(), cert_path, key_path)
+}
+
+#[tokio::test]
+async fn serves_https_when_a_certificate_is_configured() {
+    let dir = std::env::temp_dir().join(format!("offchain-tls-{}", std::process::id()));
+    let (cert_pem, cert_path, key_path) = write_certificate(&dir);
+    let addr = free_addr();
+
+    let mut server = Server(
+        Command::new(env!("CARGO_BIN_EXE_offchain"))
+            .env("ENVIRONMENT", "development")
+            .env("HOST", addr.ip().to_string())
+            .env("PORT", addr.port().to_string())
+            .env("TLS_CERT", &cert_path)
+            .env("TLS_KEY", &key_path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+
+    let client = reqwest::Client::builder()
+        .add_root_certificate(Certificate::from_pem(cert_pem.as_bytes()).unwrap())
+        .resolve("localhost", addr)
+        .build()
+        .unwrap();
+    let url = format!("https://localhost:{}/health", addr.port());
+
+    let started = Instant::now();
+    let response = loop {
+        match client.get(&url).send().await {
+            Ok(response) => break response,
+            Err(e) => {
+                if let Some(status) = server.0.try_wait().unwrap() {
+                    panic!("server exited with {} before serving", status);
+                }
+                assert!(started.elapsed() < STARTUP, "no HTTPS answer: {:?}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    };
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "OK");
+
+    // the listener speaks TLS only, and only clients trusting the certificate get through
+    let plain = reqwest::get(format!("http://{}/health", addr)).await;
+    assert!(plain.map_or(true, |response| !response.status().is_success()));
+    let untrusting = reqwest::Client::builder()
+        .resolve("localhost", addr)
+        .build()
+        .unwrap();
+    assert!(untrusting.get(&url).send().await.is_err());
+
+    let _ = std::fs::remove_dir_all(dir);
+}